@@ -0,0 +1,232 @@
+/*!
+Sending files to [`async-io`] streams, as used by `smol`.
+
+This module is only available with the `async-io` feature.
+
+[`async-io`]: https://docs.rs/async-io
+*/
+
+use ::async_io::Async;
+
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::net::TcpStream;
+
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+mod sealed {
+    use std::net::TcpStream;
+
+    pub trait Sealed {
+        /// Borrows the descriptor as a `TcpStream`, which is all the implementations need.
+        fn with_tcp_stream<R>(&self, f: impl FnOnce(&TcpStream) -> R) -> R;
+    }
+
+    impl Sealed for TcpStream {
+        #[inline]
+        fn with_tcp_stream<R>(&self, f: impl FnOnce(&TcpStream) -> R) -> R {
+            f(self)
+        }
+    }
+
+    #[cfg(unix)]
+    impl Sealed for std::os::unix::net::UnixStream {
+        fn with_tcp_stream<R>(&self, f: impl FnOnce(&TcpStream) -> R) -> R {
+            use std::mem::ManuallyDrop;
+            use std::os::unix::io::{AsRawFd, FromRawFd};
+
+            // `sendfile()` and the writes of the fallback work on any stream socket,
+            // so the descriptor is borrowed as a `TcpStream` without taking ownership of it
+            let stream = ManuallyDrop::new(unsafe { TcpStream::from_raw_fd(self.as_raw_fd()) });
+
+            f(&stream)
+        }
+    }
+}
+
+/// A socket type which files can be sent to.
+///
+/// This is implemented for `TcpStream` and on Unix platforms for `UnixStream`.
+/// This trait is sealed and cannot be implemented outside of this crate.
+pub trait Socket: sealed::Sealed {}
+
+impl Socket for TcpStream {}
+
+#[cfg(unix)]
+impl Socket for UnixStream {}
+
+/// Sends the entire contents of a file to an `async-io` stream.
+///
+/// This is the asynchronous equivalent of [`snedfile::send_file()`].
+/// The file is sent in chunks, waiting for the stream to become writable between them,
+/// and every chunk continues at the offset where the previous one stopped.
+/// The fallback, which is also used if the native `sendfile()` does not support the file or the stream,
+/// reads the file in a single task on the thread pool of the [`blocking`] crate.
+///
+/// Unlike `send_file()`, the file is always sent starting at offset `0`,
+/// and the file offset is not changed, except on Windows, where reading the file for the fallback moves it.
+///
+/// # Example
+///
+/// ```
+/// use async_io::Async;
+/// # use std::io;
+/// # use std::fs::File;
+/// # use std::net::TcpStream;
+///
+/// async fn serve_static(file: &File, stream: &Async<TcpStream>) -> io::Result<()> {
+///     snedfile::async_io::send_file(file, stream).await
+/// }
+/// ```
+///
+/// [`snedfile::send_file()`]: ../fn.send_file.html
+/// [`blocking`]: https://docs.rs/blocking
+pub async fn send_file<T: Socket>(file: &File, stream: &Async<T>) -> io::Result<()> {
+    let length = file.metadata()?.len();
+
+    send_file_imp(file, stream, length).await
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    all(target_os = "ios", feature = "ios-sendfile"),
+    target_os = "freebsd",
+    target_os = "dragonfly"
+))]
+async fn send_file_imp<T: Socket>(file: &File, stream: &Async<T>, length: u64) -> io::Result<()> {
+    let mut offset = 0;
+
+    while offset < length {
+        stream.writable().await?;
+
+        match stream.get_ref().with_tcp_stream(|stream| {
+            crate::imp::try_send_chunk(file, stream, offset, length - offset)
+        }) {
+            Ok(Some(0)) => break, // the file has been truncated
+            Ok(Some(sent)) => offset += sent,
+            // reading the file blocks, so it must not happen on the executor
+            Ok(None) => return send_blocking(file, stream, offset, length).await,
+            Err(ref e) if check_error(e.kind()) => continue,
+            Err(e) => return Err(e),
+        };
+    }
+
+    Ok(())
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    all(target_os = "ios", feature = "ios-sendfile"),
+    target_os = "freebsd",
+    target_os = "dragonfly"
+)))]
+#[inline]
+async fn send_file_imp<T: Socket>(file: &File, stream: &Async<T>, length: u64) -> io::Result<()> {
+    send_blocking(file, stream, 0, length).await
+}
+
+/// Sends the file from `offset` up to `length`, reading it on the `blocking` thread pool.
+async fn send_blocking<T: Socket>(
+    file: &File,
+    stream: &Async<T>,
+    offset: u64,
+    length: u64,
+) -> io::Result<()> {
+    use crate::BufferPool;
+    use ::blocking::Unblock;
+    use futures_lite::AsyncReadExt;
+    use std::io::Write;
+
+    // a single task reads ahead while the previous data is written
+    let mut reader = Unblock::new(Range {
+        file: file.try_clone()?,
+        offset,
+        length,
+    });
+    let mut buf = BufferPool::global().take();
+
+    loop {
+        let read = match reader.read(&mut buf).await {
+            Ok(0) => break, // the range has been read, or the file has been truncated
+            Ok(read) => read,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        let mut written = 0;
+
+        while written < read {
+            let data = &buf[written..read];
+
+            match stream
+                .write_with(|stream| stream.with_tcp_stream(|mut stream| stream.write(data)))
+                .await?
+            {
+                0 => return Err(io::Error::from(ErrorKind::WriteZero)),
+                sent => written += sent,
+            };
+        }
+    }
+
+    BufferPool::global().give(buf);
+
+    Ok(())
+}
+
+/// Reads a file from `offset` up to `length` without changing the file offset, except on Windows.
+struct Range {
+    file: File,
+    offset: u64,
+    length: u64,
+}
+
+impl io::Read for Range {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.length.saturating_sub(self.offset);
+        let limit = remaining.min(buf.len() as u64) as usize;
+
+        let read = crate::fallback::read_at(&self.file, &mut buf[..limit], self.offset)?;
+        self.offset += read as u64;
+
+        Ok(read)
+    }
+}
+
+#[inline]
+fn check_error(e: ErrorKind) -> bool {
+    e == ErrorKind::WouldBlock || e == ErrorKind::Interrupted
+}
+
+#[cfg(test)]
+mod tests {
+    use ::async_io::{block_on, Async};
+    use std::io::{Read, Write};
+
+    #[test]
+    fn send_blocking() {
+        let data: Vec<u8> = (0..4 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&data).unwrap();
+
+        let (local, mut remote) = tcp_test::channel();
+        let local = Async::new(local).unwrap();
+
+        let reader = std::thread::spawn(move || {
+            let mut received = Vec::new();
+            remote.read_to_end(&mut received).unwrap();
+            received
+        });
+
+        let length = data.len() as u64 - 1000;
+        block_on(super::send_blocking(&file, &local, 1000, length)).unwrap();
+        drop(local);
+
+        assert!(reader.join().unwrap() == data[1000..length as usize]);
+    }
+}
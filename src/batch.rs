@@ -0,0 +1,228 @@
+use crate::wait;
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::net::TcpStream;
+
+/// The error of [`send_files()`], which records how far the transfer got.
+///
+/// It can be converted into an `io::Error` of the same kind.
+///
+/// [`send_files()`]: fn.send_files.html
+#[derive(Debug)]
+pub struct SendFilesError {
+    /// The index of the file which failed.
+    pub index: usize,
+    /// The offset within the failed file at which the error occurred.
+    pub offset: u64,
+    /// The amount of bytes sent of every file up to and including the failed one.
+    pub sent: Vec<u64>,
+    /// The underlying error.
+    pub error: io::Error,
+}
+
+impl fmt::Display for SendFilesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "sending file {} failed at offset {}: {}",
+            self.index, self.offset, self.error
+        )
+    }
+}
+
+impl Error for SendFilesError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<SendFilesError> for io::Error {
+    fn from(e: SendFilesError) -> io::Error {
+        io::Error::new(e.error.kind(), e)
+    }
+}
+
+pub fn send_files(files: &[&File], stream: &mut TcpStream) -> Result<Vec<u64>, SendFilesError> {
+    let mut sent = Vec::with_capacity(files.len());
+
+    let fail = |index, offset, sent, error| SendFilesError {
+        index,
+        offset,
+        sent,
+        error,
+    };
+
+    let mut lengths = Vec::with_capacity(files.len());
+
+    for (index, file) in files.iter().enumerate() {
+        match file.metadata() {
+            Ok(metadata) => lengths.push(metadata.len()),
+            Err(e) => return Err(fail(index, 0, vec![0; index + 1], e)),
+        };
+    }
+
+    // the files are sent in full segments, even where one file ends and the next one begins,
+    // and the caller may have corked the stream itself to append more data afterwards
+    let corked = is_corked(stream).unwrap_or(false);
+    let _ = cork(stream, true);
+
+    for (index, (&file, &length)) in files.iter().zip(lengths.iter()).enumerate() {
+        let mut offset = 0;
+
+        while offset < length {
+            match crate::imp::send_chunk(file, stream, offset, length - offset) {
+                Ok(0) => break, // the file has been truncated
+                Ok(n) => offset += n,
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                    if let Err(e) = wait::writable(stream) {
+                        sent.push(offset);
+                        let _ = cork(stream, corked);
+
+                        return Err(fail(index, offset, sent, e));
+                    };
+                }
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    sent.push(offset);
+                    let _ = cork(stream, corked);
+
+                    return Err(fail(index, offset, sent, e));
+                }
+            };
+        }
+
+        sent.push(offset);
+    }
+
+    // uncorking sends the last partial segment immediately
+    if let Err(e) = cork(stream, corked) {
+        let index = files.len().saturating_sub(1);
+        let offset = sent.last().cloned().unwrap_or(0);
+
+        return Err(fail(index, offset, sent, e));
+    };
+
+    Ok(sent)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[inline]
+fn cork(stream: &TcpStream, enable: bool) -> io::Result<()> {
+    set_option(stream, libc::TCP_CORK, enable)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+#[inline]
+fn is_corked(stream: &TcpStream) -> io::Result<bool> {
+    get_option(stream, libc::TCP_CORK)
+}
+
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+))]
+#[inline]
+fn cork(stream: &TcpStream, enable: bool) -> io::Result<()> {
+    set_option(stream, libc::TCP_NOPUSH, enable)
+}
+
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+))]
+#[inline]
+fn is_corked(stream: &TcpStream) -> io::Result<bool> {
+    get_option(stream, libc::TCP_NOPUSH)
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+)))]
+#[inline]
+fn cork(_stream: &TcpStream, _enable: bool) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+)))]
+#[inline]
+fn is_corked(_stream: &TcpStream) -> io::Result<bool> {
+    Ok(false)
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+))]
+fn set_option(stream: &TcpStream, option: libc::c_int, enable: bool) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let value = enable as libc::c_int;
+
+    if unsafe {
+        libc::setsockopt(
+            stream.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            option,
+            &value as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    } == -1
+    {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+))]
+fn get_option(stream: &TcpStream, option: libc::c_int) -> io::Result<bool> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut value: libc::c_int = 0;
+    let mut length = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+
+    if unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::IPPROTO_TCP,
+            option,
+            &mut value as *mut libc::c_int as *mut libc::c_void,
+            &mut length,
+        )
+    } == -1
+    {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(value != 0)
+    }
+}
@@ -0,0 +1,339 @@
+use crate::wait;
+
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::net::TcpStream;
+
+pub fn broadcast(file: &File, streams: &[&TcpStream]) -> io::Result<Vec<io::Result<u64>>> {
+    let length = file.metadata()?.len();
+
+    broadcast_imp(file, streams, length)
+}
+
+#[cfg(target_os = "linux")]
+fn broadcast_imp(
+    file: &File,
+    streams: &[&TcpStream],
+    length: u64,
+) -> io::Result<Vec<io::Result<u64>>> {
+    use crate::pipe::PooledPipe;
+    use crate::splice;
+    use crate::unsupported;
+
+    use libc::loff_t;
+    use std::io::Error;
+    use std::os::unix::io::AsRawFd;
+    use std::time::{Duration, Instant};
+
+    /// The state of one destination.
+    struct Target<'a> {
+        stream: &'a TcpStream,
+        pipe: Option<PooledPipe>,
+        sent: u64,
+        error: Option<Error>,
+        /// The write timeout of the stream, which limits the time without progress.
+        timeout: Option<Duration>,
+        progress: Instant,
+    }
+
+    impl Target<'_> {
+        fn fail(&mut self, e: Error) {
+            self.error = Some(e);
+            self.pipe = None;
+        }
+    }
+
+    if length > loff_t::MAX as u64 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "file exceeds maximum size",
+        ));
+    };
+
+    let mut source = PooledPipe::take()?;
+    // the data of the source pipe is discarded here once it has been duplicated
+    let null = null()?;
+
+    let mut targets = Vec::with_capacity(streams.len());
+    // restores the blocking mode of the streams on drop
+    let mut modes = Vec::with_capacity(streams.len());
+
+    for &stream in streams {
+        let mut target = Target {
+            stream,
+            pipe: Some(PooledPipe::take()?),
+            sent: 0,
+            error: None,
+            timeout: None,
+            progress: Instant::now(),
+        };
+
+        // a blocking stream would stall the others inside `splice()`
+        match stream
+            .write_timeout()
+            .and_then(|timeout| Ok((timeout, NonBlocking::new(stream.as_raw_fd())?)))
+        {
+            Ok((timeout, mode)) => {
+                target.timeout = timeout;
+                modes.push(mode);
+            }
+            Err(e) => target.fail(e),
+        };
+
+        targets.push(target);
+    }
+
+    // every target pipe must be able to hold an entire chunk
+    let chunk = targets
+        .iter()
+        .filter_map(|target| target.pipe.as_ref())
+        .map(|pipe| pipe.capacity())
+        .fold(source.capacity(), usize::min) as u64;
+
+    let mut offset: loff_t = 0;
+
+    while (offset as u64) < length && targets.iter().any(|target| target.error.is_none()) {
+        let start = offset as u64;
+
+        let filled = match splice::fill(
+            &mut source,
+            file.as_raw_fd(),
+            Some(&mut offset),
+            (length - start).min(chunk) as usize,
+        ) {
+            Ok(0) => break, // the file has been truncated
+            Ok(filled) => filled,
+            Err(ref e) if check_error(e.kind()) => continue,
+            Err(ref e) if start == 0 && unsupported::check(e) => {
+                // the blocking mode is restored first
+                drop(modes);
+
+                return Ok(streams
+                    .iter()
+                    .map(|&stream| send_all(file, stream, length))
+                    .collect());
+            }
+            Err(e) => return Err(e),
+        };
+
+        let end = start + filled as u64;
+
+        for target in targets.iter_mut().filter(|target| target.error.is_none()) {
+            let pipe = target.pipe.as_mut().expect("pipe of active target");
+
+            let duplicated = loop {
+                match unsafe { libc::tee(source.read(), pipe.write(), filled, 0) } {
+                    -1 => {
+                        let e = Error::last_os_error();
+
+                        if e.kind() != ErrorKind::Interrupted {
+                            break Err(e);
+                        };
+                    }
+                    duplicated => break Ok(duplicated as usize),
+                };
+            };
+
+            match duplicated {
+                // the rest of a short duplication is sent from the file directly
+                Ok(duplicated) => pipe.buffered += duplicated,
+                Err(e) => target.fail(e),
+            };
+
+            target.progress = Instant::now();
+        }
+
+        splice::drain(&mut source, null.as_raw_fd())?;
+
+        // every destination receives the chunk as soon as it is writable,
+        // but the next chunk is only read once all of them have received this one
+        loop {
+            let now = Instant::now();
+
+            // a destination which has not made progress within its write timeout is dropped
+            for target in targets.iter_mut() {
+                if let (None, Some(timeout)) = (&target.error, target.timeout) {
+                    if target.sent < end && now >= target.progress + timeout {
+                        target.fail(Error::from(ErrorKind::TimedOut));
+                    };
+                };
+            }
+
+            let mut pending: Vec<(usize, libc::pollfd)> = targets
+                .iter()
+                .enumerate()
+                .filter(|(_, target)| target.error.is_none() && target.sent < end)
+                .map(|(index, target)| {
+                    let poll = libc::pollfd {
+                        fd: target.stream.as_raw_fd(),
+                        events: libc::POLLOUT,
+                        revents: 0,
+                    };
+
+                    (index, poll)
+                })
+                .collect();
+
+            if pending.is_empty() {
+                break;
+            };
+
+            // the timeout is rounded up, so that the earliest expiry has passed once `poll()` times out
+            let expiry = pending
+                .iter()
+                .filter_map(|&(index, _)| {
+                    let target = &targets[index];
+
+                    target.timeout.map(|timeout| target.progress + timeout)
+                })
+                .min();
+            let timeout = match expiry {
+                Some(expiry) => {
+                    let remaining =
+                        expiry.saturating_duration_since(now) + Duration::from_nanos(999_999);
+
+                    remaining.as_millis().min(libc::c_int::MAX as u128) as libc::c_int
+                }
+                None => -1,
+            };
+
+            let mut polls: Vec<libc::pollfd> = pending.iter().map(|&(_, poll)| poll).collect();
+
+            if unsafe { libc::poll(polls.as_mut_ptr(), polls.len() as libc::nfds_t, timeout) } == -1
+            {
+                let e = Error::last_os_error();
+
+                if e.kind() == ErrorKind::Interrupted {
+                    continue;
+                };
+
+                return Err(e);
+            };
+
+            for (&mut (index, _), poll) in pending.iter_mut().zip(polls.iter()) {
+                if poll.revents == 0 {
+                    continue;
+                };
+
+                let target = &mut targets[index];
+                let pipe = target.pipe.as_mut().expect("pipe of active target");
+
+                let result = if pipe.buffered > 0 {
+                    let buffered = pipe.buffered;
+
+                    splice::splice_out(
+                        pipe,
+                        target.stream.as_raw_fd(),
+                        buffered,
+                        libc::SPLICE_F_MOVE | libc::SPLICE_F_MORE | libc::SPLICE_F_NONBLOCK,
+                    )
+                    .map(|sent| sent as u64)
+                } else {
+                    crate::imp::send_chunk(file, target.stream, target.sent, end - target.sent)
+                };
+
+                match result {
+                    Ok(0) => target.fail(Error::from(ErrorKind::WriteZero)),
+                    Ok(sent) => {
+                        target.sent += sent;
+                        target.progress = Instant::now();
+                    }
+                    Err(ref e) if check_error(e.kind()) => (),
+                    Err(e) => target.fail(e),
+                };
+            }
+        }
+    }
+
+    Ok(targets
+        .into_iter()
+        .map(|target| match target.error {
+            Some(e) => Err(e),
+            None => Ok(target.sent),
+        })
+        .collect())
+}
+
+/// Returns `/dev/null`, which is opened once.
+#[cfg(target_os = "linux")]
+fn null() -> io::Result<&'static File> {
+    use std::fs::OpenOptions;
+    use std::sync::OnceLock;
+
+    static NULL: OnceLock<File> = OnceLock::new();
+
+    if let Some(null) = NULL.get() {
+        return Ok(null);
+    };
+
+    let null = OpenOptions::new().write(true).open("/dev/null")?;
+
+    Ok(NULL.get_or_init(|| null))
+}
+
+/// Puts a descriptor into non-blocking mode, and restores its original mode on drop.
+#[cfg(target_os = "linux")]
+struct NonBlocking {
+    fd: libc::c_int,
+    flags: libc::c_int,
+}
+
+#[cfg(target_os = "linux")]
+impl NonBlocking {
+    fn new(fd: libc::c_int) -> io::Result<NonBlocking> {
+        let flags = unsafe { libc::fcntl(fd, libc::F_GETFL) };
+
+        if flags == -1
+            || (flags & libc::O_NONBLOCK == 0
+                && unsafe { libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) } == -1)
+        {
+            return Err(io::Error::last_os_error());
+        };
+
+        Ok(NonBlocking { fd, flags })
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for NonBlocking {
+    fn drop(&mut self) {
+        if self.flags & libc::O_NONBLOCK == 0 {
+            unsafe { libc::fcntl(self.fd, libc::F_SETFL, self.flags) };
+        };
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+#[inline]
+fn broadcast_imp(
+    file: &File,
+    streams: &[&TcpStream],
+    length: u64,
+) -> io::Result<Vec<io::Result<u64>>> {
+    Ok(streams
+        .iter()
+        .map(|&stream| send_all(file, stream, length))
+        .collect())
+}
+
+/// Sends the entire file to one stream.
+fn send_all(file: &File, stream: &TcpStream, length: u64) -> io::Result<u64> {
+    let mut offset = 0;
+
+    while offset < length {
+        match crate::imp::send_chunk(file, stream, offset, length - offset) {
+            Ok(0) => break, // the file has been truncated
+            Ok(sent) => offset += sent,
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => wait::writable(stream)?,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+    }
+
+    Ok(offset)
+}
+
+#[inline]
+fn check_error(e: ErrorKind) -> bool {
+    e == ErrorKind::WouldBlock || e == ErrorKind::Interrupted
+}
@@ -0,0 +1,260 @@
+use std::fs::File;
+use std::io;
+
+// the platforms which have at least one of the system calls below
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+use std::{io::Error, os::unix::io::AsRawFd};
+
+/// How a transfer interacts with the page cache.
+///
+/// These are only hints to the kernel, which are silently ignored if the platform does not support them.
+///
+/// On Linux, android, FreeBSD and DragonFlyBSD `posix_fadvise()` is used,
+/// and additionally `readahead(2)` for prefetching on Linux.
+/// On MacOS and iOS only prefetching is supported using `F_RDADVISE`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Default)]
+pub enum CachePolicy {
+    /// No hints are given.
+    #[default]
+    Normal,
+    /// The file is read sequentially, which allows a larger read-ahead window.
+    Sequential,
+    /// The file is sent once and not expected to be needed again soon.
+    ///
+    /// After the transfer, its pages are dropped from the page cache,
+    /// so that one-off downloads of large files do not evict frequently used files.
+    NoReuse,
+    /// The entire file is read into the page cache before the transfer starts.
+    Prefetch,
+}
+
+/// The result of [`send_exact_nodiskio()`].
+///
+/// [`send_exact_nodiskio()`]: fn.send_exact_nodiskio.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum NoDiskIo {
+    /// The amount of bytes sent, all of which were in the page cache.
+    Sent(u64),
+    /// The bytes at the offset are not in the page cache,
+    /// so sending them would block on disk I/O.
+    /// Nothing has been sent.
+    WouldBlockOnDisk,
+}
+
+/// Gives the hints of a policy which apply before a transfer of `length` bytes.
+pub fn before(file: &File, length: u64, policy: CachePolicy) {
+    let _ = match policy {
+        CachePolicy::Normal => Ok(()),
+        CachePolicy::Sequential => advise(file, 0, 0, Advice::Sequential),
+        CachePolicy::NoReuse => advise(file, 0, 0, Advice::NoReuse),
+        CachePolicy::Prefetch => prefetch(file, 0, length),
+    };
+}
+
+/// Gives the hints of a policy which apply after a transfer.
+pub fn after(file: &File, policy: CachePolicy) {
+    if policy == CachePolicy::NoReuse {
+        let _ = advise(file, 0, 0, Advice::DontNeed);
+    };
+}
+
+#[derive(Copy, Clone)]
+enum Advice {
+    Sequential,
+    NoReuse,
+    DontNeed,
+    // only used for prefetching where the platform has no better mechanism
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "ios")))]
+    WillNeed,
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+))]
+fn advise(file: &File, offset: u64, length: u64, advice: Advice) -> io::Result<()> {
+    let advice = match advice {
+        Advice::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+        Advice::NoReuse => libc::POSIX_FADV_NOREUSE,
+        Advice::DontNeed => libc::POSIX_FADV_DONTNEED,
+        #[cfg(not(target_os = "linux"))]
+        Advice::WillNeed => libc::POSIX_FADV_WILLNEED,
+    };
+
+    // a length of 0 means until the end of the file, so lengths that do not fit are treated like that
+    let length = if length > libc::off_t::MAX as u64 {
+        0
+    } else {
+        length as libc::off_t
+    };
+
+    if offset > libc::off_t::MAX as u64 {
+        return Ok(());
+    };
+
+    // `posix_fadvise()` returns the error instead of setting `errno`
+    match unsafe { libc::posix_fadvise(file.as_raw_fd(), offset as libc::off_t, length, advice) } {
+        0 => Ok(()),
+        e => Err(Error::from_raw_os_error(e)),
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "dragonfly"
+)))]
+fn advise(_file: &File, _offset: u64, _length: u64, _advice: Advice) -> io::Result<()> {
+    Ok(())
+}
+
+/// Reads a part of a file into the page cache.
+#[cfg(target_os = "linux")]
+pub fn prefetch(file: &File, offset: u64, length: u64) -> io::Result<()> {
+    if offset > libc::off64_t::MAX as u64 {
+        return Ok(());
+    };
+
+    let length = if length > libc::size_t::MAX as u64 {
+        libc::size_t::MAX
+    } else {
+        length as libc::size_t
+    };
+
+    if unsafe { libc::readahead(file.as_raw_fd(), offset as libc::off64_t, length) } == -1 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Reads a part of a file into the page cache.
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+pub fn prefetch(file: &File, offset: u64, length: u64) -> io::Result<()> {
+    if offset > libc::off_t::MAX as u64 {
+        return Ok(());
+    };
+
+    let advisory = libc::radvisory {
+        ra_offset: offset as libc::off_t,
+        ra_count: length.min(libc::c_int::MAX as u64) as libc::c_int,
+    };
+
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_RDADVISE, &advisory) } == -1 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Reads a part of a file into the page cache.
+#[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "ios")))]
+pub fn prefetch(file: &File, offset: u64, length: u64) -> io::Result<()> {
+    advise(file, offset, length, Advice::WillNeed)
+}
+
+/// Returns the amount of bytes directly following `offset` that are in the page cache,
+/// checking at most 16 megabytes.
+///
+/// Bytes past the end of the file count as cached, because they do not need to be read from the disk.
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd"
+))]
+pub fn resident(file: &File, offset: u64, length: u64) -> io::Result<u64> {
+    /// The maximum amount of bytes checked at once.
+    const MAX_RESIDENCY_CHECK: u64 = 16 * 1024 * 1024;
+
+    let end = offset
+        .saturating_add(length.min(MAX_RESIDENCY_CHECK))
+        .min(file.metadata()?.len());
+
+    if offset >= end {
+        return Ok(length);
+    };
+
+    let page = page_size();
+    let aligned = offset - offset % page;
+
+    if aligned > libc::off_t::MAX as u64 {
+        // the residency cannot be checked, so the bytes are assumed to be cached
+        return Ok(length);
+    };
+
+    let map_length = (end - aligned) as usize;
+    let ptr = unsafe {
+        libc::mmap(
+            std::ptr::null_mut(),
+            map_length,
+            libc::PROT_READ,
+            libc::MAP_SHARED,
+            file.as_raw_fd(),
+            aligned as libc::off_t,
+        )
+    };
+
+    if ptr == libc::MAP_FAILED {
+        return Err(Error::last_os_error());
+    };
+
+    // the lowest bit of every entry tells whether the page is resident
+    let mut pages = vec![0u8; map_length.div_ceil(page as usize)];
+    let result = unsafe { libc::mincore(ptr as _, map_length, pages.as_mut_ptr() as _) };
+    let error = Error::last_os_error();
+
+    unsafe {
+        libc::munmap(ptr, map_length);
+    }
+
+    if result == -1 {
+        return Err(error);
+    };
+
+    let resident_pages = pages.iter().take_while(|&&page| page & 1 != 0).count() as u64;
+
+    Ok((aligned + resident_pages * page).min(end) - offset)
+}
+
+/// Returns the amount of bytes directly following `offset` that are in the page cache.
+///
+/// This platform provides no way to check, so all bytes are assumed to be cached.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "freebsd",
+    target_os = "dragonfly",
+    target_os = "netbsd",
+    target_os = "openbsd"
+)))]
+pub fn resident(_file: &File, _offset: u64, length: u64) -> io::Result<u64> {
+    Ok(length)
+}
+
+/// Returns the size of a memory page.
+#[cfg(unix)]
+pub fn page_size() -> u64 {
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as u64,
+        _ => 4096,
+    }
+}
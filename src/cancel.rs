@@ -0,0 +1,180 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::net::TcpStream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// The most bytes sent by one system call of a cancellable transfer,
+/// so that a blocking stream notices the cancellation soon enough.
+const CHUNK: u64 = 1024 * 1024;
+
+/// Cancels transfers from another thread.
+///
+/// Clones of a token share its state, so a token can be cloned into every transfer
+/// and all of them are cancelled at once using [`cancel()`].
+///
+/// A cancelled transfer stops after its current system call,
+/// and waits for the stream to become writable are woken up immediately.
+/// Every system call sends at most one megabyte, but note that it still blocks
+/// until the peer has received enough of it on a blocking stream.
+///
+/// # Example
+///
+/// ```
+/// use snedfile::{send_file_cancellable, CancelToken};
+/// # use std::io;
+/// # use std::fs::File;
+/// # use std::net::TcpStream;
+/// use std::thread::{self, JoinHandle};
+///
+/// fn serve(file: File, mut stream: TcpStream, shutdown: &CancelToken) -> JoinHandle<io::Result<u64>> {
+///     let shutdown = shutdown.clone();
+///
+///     thread::spawn(move || send_file_cancellable(&file, &mut stream, &shutdown))
+/// }
+/// ```
+///
+/// [`cancel()`]: #method.cancel
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    cancelled: AtomicBool,
+    /// A pipe which becomes readable once the token is cancelled, to wake up `poll()`.
+    #[cfg(unix)]
+    pipe: Option<[libc::c_int; 2]>,
+}
+
+impl CancelToken {
+    /// Creates a token which has not been cancelled.
+    #[inline]
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Cancels every transfer using this token or one of its clones.
+    pub fn cancel(&self) {
+        if self.inner.cancelled.swap(true, Ordering::SeqCst) {
+            return;
+        };
+
+        // the byte is never read, so the pipe stays readable
+        #[cfg(unix)]
+        {
+            if let Some([_, write]) = self.inner.pipe {
+                unsafe { libc::write(write, [1u8].as_ptr() as *const libc::c_void, 1) };
+            };
+        }
+    }
+
+    /// Returns whether the token has been cancelled.
+    #[inline]
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Returns a [`Cancelled`] error if the token has been cancelled.
+    #[inline]
+    pub(crate) fn check(&self, sent: u64) -> io::Result<()> {
+        if self.is_cancelled() {
+            Err(Cancelled { sent }.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Returns the descriptor which becomes readable once the token is cancelled,
+    /// or `-1` if there is none and the token has to be checked periodically.
+    #[cfg(unix)]
+    #[inline]
+    pub(crate) fn fd(&self) -> libc::c_int {
+        match self.inner.pipe {
+            Some([read, _]) => read,
+            None => -1,
+        }
+    }
+}
+
+impl Default for Inner {
+    fn default() -> Inner {
+        Inner {
+            cancelled: AtomicBool::new(false),
+            #[cfg(unix)]
+            pipe: pipe(),
+        }
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Inner {
+    fn drop(&mut self) {
+        if let Some([read, write]) = self.pipe {
+            unsafe {
+                libc::close(read);
+                libc::close(write);
+            };
+        };
+    }
+}
+
+/// Creates a non-blocking pipe, or returns `None` if that fails.
+#[cfg(unix)]
+fn pipe() -> Option<[libc::c_int; 2]> {
+    let mut fds = [0; 2];
+
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } == -1 {
+        return None;
+    };
+
+    for &fd in &fds {
+        unsafe {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+            libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK);
+        };
+    }
+
+    Some(fds)
+}
+
+/// The error of a transfer cancelled using a [`CancelToken`].
+///
+/// It is returned wrapped in an `io::Error` of kind `Other`,
+/// and can be recovered using `get_ref()` and `downcast_ref()`.
+///
+/// [`CancelToken`]: struct.CancelToken.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Cancelled {
+    /// The amount of bytes sent before the transfer was cancelled.
+    pub sent: u64,
+}
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "transfer cancelled after {} bytes", self.sent)
+    }
+}
+
+impl Error for Cancelled {}
+
+impl From<Cancelled> for io::Error {
+    #[inline]
+    fn from(e: Cancelled) -> io::Error {
+        io::Error::other(e)
+    }
+}
+
+/// Sends at most one bounded chunk using a single system call.
+#[inline]
+pub(crate) fn send_chunk(
+    file: &File,
+    stream: &TcpStream,
+    offset: u64,
+    length: u64,
+) -> io::Result<u64> {
+    crate::imp::send_chunk(file, stream, offset, length.min(CHUNK))
+}
@@ -0,0 +1,58 @@
+//! Storage shared by the checkpoints of [`ship`] and [`resume`].
+//!
+//! [`ship`]: ../ship/index.html
+//! [`resume`]: ../resume/index.html
+
+use std::ffi::OsString;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Error, ErrorKind, Write};
+use std::path::Path;
+
+/// Reads and decodes the checkpoint stored at `path`.
+///
+/// If the file does not exist, `None` is returned.
+/// If it cannot be decoded, an error of kind `InvalidData` is returned.
+pub fn load<T, F>(path: &Path, decode: F) -> io::Result<Option<T>>
+where
+    F: FnOnce(&str) -> Option<T>,
+{
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+
+    decode(&contents)
+        .map(Some)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "malformed checkpoint"))
+}
+
+/// Writes `contents` to a temporary file next to `path` and renames it to `path`,
+/// so that the previous contents stay intact if the process dies in between.
+pub fn store(path: &Path, contents: &str) -> io::Result<()> {
+    let mut name = OsString::from(path.file_name().unwrap_or_default());
+    name.push(".tmp");
+    let temporary = path.with_file_name(name);
+
+    let mut file = File::create(&temporary)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+
+    fs::rename(&temporary, path)
+}
+
+/// Returns the device and inode of a file, which identify it across renames.
+#[cfg(unix)]
+#[inline]
+pub fn file_id(metadata: &Metadata) -> (u64, u64) {
+    use std::os::unix::fs::MetadataExt;
+
+    (metadata.dev(), metadata.ino())
+}
+
+/// Without inodes, files cannot be told apart.
+#[cfg(not(unix))]
+#[inline]
+pub fn file_id(_metadata: &Metadata) -> (u64, u64) {
+    (0, 0)
+}
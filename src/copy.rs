@@ -0,0 +1,238 @@
+use std::fs::File;
+use std::io::{self, ErrorKind, Write};
+use std::ops::{Bound, RangeBounds};
+
+/// The method [`copy_file()`] used to copy the data.
+///
+/// [`copy_file()`]: fn.copy_file.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum CopyMethod {
+    /// The data is shared between both files using a reflink,
+    /// which is only copied once it is modified.
+    Reflink,
+    /// The data was copied by the kernel using `copy_file_range()`,
+    /// which can also use server-side copies of network file systems.
+    CopyFileRange,
+    /// The data was copied by the kernel using `sendfile()`.
+    Sendfile,
+    /// The data was copied through a buffer in userspace.
+    Buffered,
+}
+
+pub fn copy_file<R: RangeBounds<u64>>(
+    src: &File,
+    dst: &File,
+    range: R,
+) -> io::Result<(u64, CopyMethod)> {
+    let src_length = src.metadata()?.len();
+
+    let start = match range.start_bound() {
+        Bound::Included(&start) => start,
+        Bound::Excluded(&start) => start.saturating_add(1),
+        Bound::Unbounded => 0,
+    };
+
+    let end = match range.end_bound() {
+        Bound::Included(&end) => end.saturating_add(1),
+        Bound::Excluded(&end) => end,
+        Bound::Unbounded => src_length,
+    };
+
+    let start = start.min(src_length);
+    let length = end.min(src_length).saturating_sub(start);
+
+    copy_imp(src, dst, start, length, src_length)
+}
+
+#[cfg(target_os = "linux")]
+fn copy_imp(
+    src: &File,
+    dst: &File,
+    start: u64,
+    length: u64,
+    src_length: u64,
+) -> io::Result<(u64, CopyMethod)> {
+    use libc::loff_t;
+
+    // the kernel interfaces use signed offsets
+    if start + length > loff_t::MAX as u64 {
+        return Ok((
+            copy_buffered(src, dst, start, length)?,
+            CopyMethod::Buffered,
+        ));
+    };
+
+    if let Some(copied) = reflink(src, dst, start, length, src_length)? {
+        return Ok((copied, CopyMethod::Reflink));
+    };
+
+    let copy_file_range = |src, offset: &mut loff_t, dst, length| unsafe {
+        libc::copy_file_range(src, offset, dst, std::ptr::null_mut(), length, 0)
+    };
+
+    if let Some(copied) = copy_kernel(src, dst, start, length, copy_file_range)? {
+        return Ok((copied, CopyMethod::CopyFileRange));
+    };
+
+    let sendfile = |src, offset: &mut loff_t, dst, length| {
+        let mut sendfile_offset = *offset as libc::off_t;
+        let result = unsafe { libc::sendfile(dst, src, &mut sendfile_offset, length) };
+        *offset = sendfile_offset as loff_t;
+
+        result
+    };
+
+    // `off_t` may only have 32 bits
+    if start + length <= libc::off_t::MAX as u64 {
+        if let Some(copied) = copy_kernel(src, dst, start, length, sendfile)? {
+            return Ok((copied, CopyMethod::Sendfile));
+        };
+    };
+
+    Ok((
+        copy_buffered(src, dst, start, length)?,
+        CopyMethod::Buffered,
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+#[inline]
+fn copy_imp(
+    src: &File,
+    dst: &File,
+    start: u64,
+    length: u64,
+    _src_length: u64,
+) -> io::Result<(u64, CopyMethod)> {
+    Ok((
+        copy_buffered(src, dst, start, length)?,
+        CopyMethod::Buffered,
+    ))
+}
+
+/// Shares the range with `dst` at its current offset, returning `None` if reflinks are unsupported.
+#[cfg(target_os = "linux")]
+fn reflink(
+    src: &File,
+    dst: &File,
+    start: u64,
+    length: u64,
+    src_length: u64,
+) -> io::Result<Option<u64>> {
+    use std::io::{Error, Seek, SeekFrom};
+    use std::os::unix::io::AsRawFd;
+
+    // a length of `0` means until the end of the file for `FICLONERANGE`
+    if length == 0 {
+        return Ok(None);
+    };
+
+    let position = (&*dst).stream_position()?;
+
+    let result = if start == 0 && length == src_length && position == 0 {
+        unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONE, src.as_raw_fd()) }
+    } else {
+        let range = libc::file_clone_range {
+            src_fd: src.as_raw_fd() as i64,
+            src_offset: start,
+            src_length: length,
+            dest_offset: position,
+        };
+
+        unsafe { libc::ioctl(dst.as_raw_fd(), libc::FICLONERANGE, &range) }
+    };
+
+    if result == -1 {
+        let e = Error::last_os_error();
+
+        // besides unsupported file systems, ranges which are not aligned to blocks cannot be shared
+        return if unsupported(&e) || e.raw_os_error() == Some(libc::ENOTTY) {
+            Ok(None)
+        } else {
+            Err(e)
+        };
+    };
+
+    (&*dst).seek(SeekFrom::Start(position + length))?;
+
+    Ok(Some(length))
+}
+
+/// Copies the range to `dst` at its current offset using `copy`,
+/// returning `None` if it does not support the files.
+#[cfg(target_os = "linux")]
+fn copy_kernel(
+    src: &File,
+    dst: &File,
+    start: u64,
+    length: u64,
+    copy: impl Fn(libc::c_int, &mut libc::loff_t, libc::c_int, usize) -> isize,
+) -> io::Result<Option<u64>> {
+    use std::io::Error;
+    use std::os::unix::io::AsRawFd;
+
+    // according to the Linux docs, 0x7ffff000 is the maximum length for one call
+    const MAX_CHUNK: u64 = 0x7ffff000;
+
+    let mut offset = start as libc::loff_t;
+    let mut copied = 0;
+
+    while copied < length {
+        match copy(
+            src.as_raw_fd(),
+            &mut offset,
+            dst.as_raw_fd(),
+            (length - copied).min(MAX_CHUNK) as usize,
+        ) {
+            -1 => {
+                let e = Error::last_os_error();
+
+                if e.kind() == ErrorKind::Interrupted {
+                    continue;
+                };
+
+                return if copied == 0 && unsupported(&e) {
+                    Ok(None)
+                } else {
+                    Err(e)
+                };
+            }
+            0 => break, // the file has been truncated
+            n => copied += n as u64,
+        };
+    }
+
+    Ok(Some(copied))
+}
+
+/// Checks if the error means that the method is not supported for the files.
+#[cfg(target_os = "linux")]
+#[inline]
+fn unsupported(e: &io::Error) -> bool {
+    crate::unsupported::check(e) || e.raw_os_error() == Some(libc::EXDEV)
+}
+
+/// Copies the range to `dst` at its current offset using a buffer from the global pool.
+fn copy_buffered(src: &File, mut dst: &File, start: u64, length: u64) -> io::Result<u64> {
+    let pool = crate::BufferPool::global();
+    let mut buf = pool.take();
+    let mut copied = 0;
+
+    while copied < length {
+        let chunk = (length - copied).min(buf.len() as u64) as usize;
+
+        match crate::fallback::read_at(src, &mut buf[..chunk], start + copied) {
+            Ok(0) => break, // the file has been truncated
+            Ok(read) => {
+                dst.write_all(&buf[..read])?;
+                copied += read as u64;
+            }
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+    }
+
+    pool.give(buf);
+
+    Ok(copied)
+}
@@ -5,6 +5,9 @@ use std::fs::File;
 use std::io::{self, BufReader, ErrorKind, Read, Seek, SeekFrom, Write};
 use std::net::TcpStream;
 
+use crate::cache;
+use crate::{BufferPool, NoDiskIo};
+
 pub fn send_file(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
     let length = file.metadata()?.len();
 
@@ -15,6 +18,15 @@ pub fn send_file(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
     send_file_imp(file, stream, length)
 }
 
+/// Sends the entire file regardless of the current file offset.
+///
+/// This is used by the native implementations if `sendfile()` turns out to be unsupported.
+pub fn send_file_from_start(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
+    file.seek(SeekFrom::Start(0))?;
+
+    send_file(file, stream)
+}
+
 pub fn send_exact(
     file: &mut File,
     stream: &mut TcpStream,
@@ -25,7 +37,60 @@ pub fn send_exact(
     io::copy(&mut file.take(length), stream)
 }
 
-#[cfg(not(any(feature = "fallback-bufreader", feature = "fallback-buf")))]
+/// Sends at most `length` bytes at `offset` using a single write.
+///
+/// The amount of bytes sent is returned, which is `0` at the end of the file.
+/// Errors like `WouldBlock` are only returned if nothing has been sent.
+pub fn send_chunk(file: &File, stream: &TcpStream, offset: u64, length: u64) -> io::Result<u64> {
+    let pool = BufferPool::global();
+    let mut buf = pool.take();
+
+    let length = length.min(buf.len() as u64) as usize;
+    let result = match read_at(file, &mut buf[..length], offset) {
+        Ok(0) => Ok(0),
+        Ok(read) => (&*stream).write(&buf[..read]).map(|sent| sent as u64),
+        Err(e) => Err(e),
+    };
+
+    pool.give(buf);
+
+    result
+}
+
+#[cfg(unix)]
+pub fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::unix::fs::FileExt::read_at(file, buf, offset)
+}
+
+#[cfg(windows)]
+pub fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    std::os::windows::fs::FileExt::seek_read(file, buf, offset)
+}
+
+#[cfg(not(any(unix, windows)))]
+pub fn read_at(mut file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    file.seek(SeekFrom::Start(offset))?;
+    file.read(buf)
+}
+
+pub fn send_exact_nodiskio(
+    file: &mut File,
+    stream: &mut TcpStream,
+    length: u64,
+    offset: u64,
+) -> io::Result<NoDiskIo> {
+    match cache::resident(file, offset, length)? {
+        0 if length != 0 => Ok(NoDiskIo::WouldBlockOnDisk),
+        resident => send_exact(file, stream, resident, offset).map(NoDiskIo::Sent),
+    }
+}
+
+#[cfg(not(any(
+    feature = "fallback-bufreader",
+    feature = "fallback-buf",
+    all(unix, feature = "fallback-mmap"),
+    feature = "fallback-readahead"
+)))]
 pub fn send_file_imp(file: &mut File, stream: &mut TcpStream, length: u64) -> io::Result<()> {
     let mut sent = io::copy(file, stream)?;
 
@@ -49,32 +114,57 @@ pub fn send_file_imp(file: &mut File, stream: &mut TcpStream, length: u64) -> io
     Ok(())
 }
 
-#[cfg(all(feature = "fallback-buf", not(feature = "large-files")))]
-pub fn send_file_imp(file: &mut File, stream: &mut TcpStream, length: u64) -> io::Result<()> {
-    let mut buf = Vec::with_capacity(length as usize);
+#[cfg(feature = "fallback-buf")]
+pub fn send_file_imp(file: &mut File, stream: &mut TcpStream, _length: u64) -> io::Result<()> {
+    BufferPool::global().copy(file, stream)?;
 
-    file.read_to_end(&mut buf)?;
-    stream.write_all(&buf)?;
+    Ok(())
+}
+
+#[cfg(all(unix, feature = "fallback-mmap"))]
+pub fn send_file_imp(file: &mut File, stream: &mut TcpStream, _length: u64) -> io::Result<()> {
+    crate::mmap::send_file(file, stream, crate::mmap::DEFAULT_WINDOW)
+}
+
+#[cfg(feature = "fallback-readahead")]
+pub fn send_file_imp(file: &mut File, stream: &mut TcpStream, _length: u64) -> io::Result<()> {
+    BufferPool::global().copy_read_ahead(file, stream)?;
 
     Ok(())
 }
 
-#[cfg(all(feature = "fallback-buf", feature = "large-files"))]
-pub fn send_file_imp(file: &mut File, stream: &mut TcpStream, length: u64) -> io::Result<()> {
-    let mut remaining = length.checked_sub(usize::max_value() as u64).unwrap_or(0);
+#[cfg(feature = "fallback-readahead")]
+pub fn copy_to_end(file: &mut File, stream: &mut TcpStream, offset: u64) -> io::Result<()> {
+    file.seek(SeekFrom::Start(offset))?;
 
-    let mut buf = Vec::with_capacity(length as usize);
+    BufferPool::global().copy_read_ahead(file, &mut Blocking(stream))?;
 
-    file.read_to_end(&mut buf)?;
-    stream.write_all(&buf)?;
+    Ok(())
+}
 
-    while remaining > 0 {
-        remaining -= io::copy(file, stream)?;
+/// Waits for a non-blocking stream to become writable instead of failing with `WouldBlock`,
+/// so that `write_all()` neither loses nor repeats the bytes of a partial write.
+#[cfg(feature = "fallback-readahead")]
+struct Blocking<'a>(&'a TcpStream);
+
+#[cfg(feature = "fallback-readahead")]
+impl Write for Blocking<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        loop {
+            match self.0.write(buf) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => crate::wait::writable(self.0)?,
+                result => return result,
+            };
+        }
     }
 
-    Ok(())
+    #[inline]
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
 }
 
+#[cfg(not(feature = "fallback-readahead"))]
 pub fn copy_to_end(file: &mut File, stream: &mut TcpStream, offset: u64) -> io::Result<()> {
     file.seek(SeekFrom::Start(offset))?;
 
@@ -141,4 +231,26 @@ mod tests {
         b.read_exact(&mut buf).unwrap();
         assert_eq!(&data[3..], &buf);
     }
+
+    #[cfg(feature = "fallback-readahead")]
+    #[test]
+    fn copy_to_end_nonblocking() {
+        let mut file = tempfile::tempfile().unwrap();
+        let (mut a, mut b) = tcp_test::channel();
+        let data: Vec<u8> = (0..4 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+
+        file.write_all(&data).unwrap();
+        a.set_nonblocking(true).unwrap();
+
+        let receiver = std::thread::spawn(move || {
+            let mut buf = Vec::new();
+            b.read_to_end(&mut buf).unwrap();
+            buf
+        });
+
+        super::copy_to_end(&mut file, &mut a, 3).unwrap();
+        drop(a);
+
+        assert!(receiver.join().unwrap() == data[3..]);
+    }
 }
@@ -0,0 +1,155 @@
+use crate::{cancel, wait, CancelToken};
+
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::net::TcpStream;
+use std::time::Duration;
+
+/// The longest time between two checks for growth of the file.
+const INTERVAL: Duration = Duration::from_millis(250);
+
+pub fn follow(file: &File, stream: &mut TcpStream, cancel: &CancelToken) -> io::Result<u64> {
+    let watcher = Watcher::new(file);
+
+    let mut offset = 0;
+    let mut sent = 0;
+
+    loop {
+        let length = file.metadata()?.len();
+
+        // like `tail -f`, a truncated file is sent again from the start
+        if length < offset {
+            offset = 0;
+        };
+
+        let start = offset;
+        let result = send_range(file, stream, &mut offset, length, cancel);
+        sent += offset - start;
+        result?;
+
+        if cancel.is_cancelled() {
+            return Ok(sent);
+        };
+
+        watcher.wait(cancel)?;
+    }
+}
+
+/// Sends the file from `offset` up to `length`, advancing `offset` by the amount of bytes sent.
+///
+/// This returns early if the transfer is cancelled or the file has been truncated.
+pub(crate) fn send_range(
+    file: &File,
+    stream: &TcpStream,
+    offset: &mut u64,
+    length: u64,
+    cancel: &CancelToken,
+) -> io::Result<()> {
+    while *offset < length {
+        if cancel.is_cancelled() {
+            break;
+        };
+
+        match cancel::send_chunk(file, stream, *offset, length - *offset) {
+            Ok(0) => break, // the file has been truncated
+            Ok(n) => *offset += n,
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                wait::writable_or_cancelled(stream, cancel)?
+            }
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+    }
+
+    Ok(())
+}
+
+/// Waits for changes of a file using `inotify`.
+#[cfg(target_os = "linux")]
+pub(crate) struct Watcher {
+    /// The `inotify` instance, or `None` if it could not be created and the file is polled instead.
+    fd: Option<libc::c_int>,
+}
+
+#[cfg(target_os = "linux")]
+impl Watcher {
+    pub(crate) fn new(file: &File) -> Watcher {
+        use std::ffi::CString;
+        use std::os::unix::io::AsRawFd;
+
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+
+        if fd == -1 {
+            return Watcher { fd: None };
+        };
+
+        // the link in `/proc` refers to the file itself, even if it has been renamed or has no path at all
+        let path = CString::new(format!("/proc/self/fd/{}", file.as_raw_fd())).expect("path");
+
+        if unsafe {
+            libc::inotify_add_watch(
+                fd,
+                path.as_ptr(),
+                libc::IN_MODIFY | libc::IN_ATTRIB | libc::IN_CLOSE_WRITE | libc::IN_MOVE_SELF,
+            )
+        } == -1
+        {
+            unsafe { libc::close(fd) };
+
+            return Watcher { fd: None };
+        };
+
+        Watcher { fd: Some(fd) }
+    }
+
+    /// Blocks until the file has changed or the token has been cancelled, or at most for `INTERVAL`.
+    pub(crate) fn wait(&self, cancel: &CancelToken) -> io::Result<()> {
+        let fd = self.fd.unwrap_or(-1);
+
+        wait::readable_or_cancelled(fd, cancel, INTERVAL)?;
+
+        // the events are only used for waking up, so they are discarded
+        if fd != -1 {
+            let mut buf = [0u8; 4096];
+
+            while unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) } > 0 {}
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        if let Some(fd) = self.fd {
+            unsafe { libc::close(fd) };
+        };
+    }
+}
+
+/// Polls the length of a file.
+#[cfg(not(target_os = "linux"))]
+pub(crate) struct Watcher;
+
+#[cfg(not(target_os = "linux"))]
+impl Watcher {
+    #[inline]
+    pub(crate) fn new(_file: &File) -> Watcher {
+        Watcher
+    }
+
+    /// Blocks for `INTERVAL` unless the token is cancelled, after which the length of the file is checked again.
+    #[cfg(unix)]
+    #[inline]
+    pub(crate) fn wait(&self, cancel: &CancelToken) -> io::Result<()> {
+        wait::readable_or_cancelled(-1, cancel, INTERVAL)
+    }
+
+    /// Blocks for `INTERVAL` unless the token is cancelled, after which the length of the file is checked again.
+    #[cfg(not(unix))]
+    #[inline]
+    pub(crate) fn wait(&self, cancel: &CancelToken) -> io::Result<()> {
+        wait::sleep_or_cancelled(cancel, INTERVAL)
+    }
+}
@@ -0,0 +1,450 @@
+/*!
+A small protocol for transferring single files between services.
+
+Every transfer starts with a header, which is followed by the contents of the file.
+All integers are big-endian.
+
+| Field    | Size     | Description                                               |
+|----------|----------|-----------------------------------------------------------|
+| magic    | 4        | `SNED`                                                    |
+| version  | 1        | `1`                                                       |
+| flags    | 1        | `1` if a name is present, `2` if a checksum is present    |
+| length   | 8        | The length of the file                                    |
+| name     | 2 + n    | The length of the name, and the name in UTF-8, if present |
+| checksum | 4        | The CRC-32 (IEEE) of the file, if present                 |
+
+[`send_framed()`] sends the file like [`send_file()`], and [`receive_framed()`] receives it using [`recv_file()`],
+so that the contents are not copied into userspace where the platform supports it.
+Computing and verifying the checksum requires reading the file, though.
+
+[`send_framed()`]: fn.send_framed.html
+[`receive_framed()`]: fn.receive_framed.html
+[`send_file()`]: ../fn.send_file.html
+[`recv_file()`]: ../fn.recv_file.html
+*/
+
+use crate::{wait, RecvOptions};
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Error, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MAGIC: &[u8; 4] = b"SNED";
+const VERSION: u8 = 1;
+
+const FLAG_NAME: u8 = 1;
+const FLAG_CHECKSUM: u8 = 2;
+
+/// The header of a transfer.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Header {
+    /// The length of the file.
+    pub length: u64,
+    /// The name of the file.
+    pub name: Option<String>,
+    /// The CRC-32 (IEEE) of the file.
+    pub checksum: Option<u32>,
+}
+
+/// A file received using [`receive_framed()`].
+///
+/// [`receive_framed()`]: fn.receive_framed.html
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Received {
+    /// The path of the file, inside of the directory passed to `receive_framed()`.
+    pub path: PathBuf,
+    /// The header sent along with the file, which has been validated.
+    pub header: Header,
+}
+
+/// Sends the header and the entire contents of a file.
+///
+/// If `name` is given, it must be a plain file name without any directories,
+/// and at most 65535 bytes long.
+/// If `checksum` is `true`, the file is read once before sending it to compute the checksum.
+///
+/// The file is always sent starting at offset `0`, and the file offset is not changed.
+/// Exactly the length in the header is sent, so if the file is truncated in the meantime,
+/// an error of kind `UnexpectedEof` is returned, and data appended in the meantime is not sent.
+///
+/// # Example
+///
+/// ```
+/// use snedfile::framed::send_framed;
+/// # use std::io;
+/// # use std::fs::File;
+/// # use std::net::TcpStream;
+///
+/// fn upload(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
+///     send_framed(file, stream, Some("artifact.tar"), true)
+/// }
+/// ```
+pub fn send_framed(
+    file: &mut File,
+    stream: &mut TcpStream,
+    name: Option<&str>,
+    checksum: bool,
+) -> io::Result<()> {
+    if let Some(name) = name {
+        check_name(name)?;
+    };
+
+    let length = file.metadata()?.len();
+
+    let header = Header {
+        length,
+        name: name.map(String::from),
+        checksum: if checksum {
+            Some(crc32(file, length)?)
+        } else {
+            None
+        },
+    };
+
+    write_all(stream, &encode(&header))?;
+
+    send_body(file, stream, header.length)
+}
+
+/// Writes the entire buffer, waiting for a non-blocking stream to become writable.
+fn write_all(mut stream: &TcpStream, mut buf: &[u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        match stream.write(buf) {
+            Ok(0) => return Err(Error::from(ErrorKind::WriteZero)),
+            Ok(n) => buf = &buf[n..],
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => wait::writable(stream)?,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+    }
+
+    Ok(())
+}
+
+/// Sends exactly `length` bytes of the file starting at offset `0`.
+fn send_body(file: &File, stream: &TcpStream, length: u64) -> io::Result<()> {
+    let mut offset = 0;
+
+    while offset < length {
+        match crate::imp::send_chunk(file, stream, offset, length - offset) {
+            Ok(0) => {
+                return Err(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "file is shorter than the length in the header",
+                ))
+            }
+            Ok(sent) => offset += sent,
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => wait::writable(stream)?,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+    }
+
+    Ok(())
+}
+
+/// Receives a file sent using [`send_framed()`] into a directory.
+///
+/// The file is named like the header says, or gets a unique name if the header contains none.
+/// It is never overwritten if it already exists, in which case an error of kind `AlreadyExists` is returned.
+///
+/// The header is validated, and an error of kind `InvalidData` is returned if it is malformed,
+/// or if the received file does not match the checksum.
+/// If the stream ends before the entire file has been received, an error of kind `UnexpectedEof` is returned.
+/// In both cases the file is removed.
+///
+/// # Example
+///
+/// ```
+/// use snedfile::framed::receive_framed;
+/// # use std::io;
+/// # use std::net::TcpStream;
+/// # use std::path::Path;
+///
+/// fn accept_upload(stream: &mut TcpStream) -> io::Result<()> {
+///     let received = receive_framed(stream, Path::new("uploads"))?;
+///     println!("received {}", received.path.display());
+///
+///     Ok(())
+/// }
+/// ```
+///
+/// [`send_framed()`]: fn.send_framed.html
+pub fn receive_framed(stream: &mut TcpStream, dir: &Path) -> io::Result<Received> {
+    let header = decode(stream)?;
+
+    let path = dir.join(match header.name {
+        Some(ref name) => name.clone(),
+        None => unique_name(),
+    });
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+
+    let result = receive_body(stream, &mut file, &header);
+
+    if result.is_err() {
+        drop(file);
+        let _ = fs::remove_file(&path);
+    };
+
+    result.map(|()| Received { path, header })
+}
+
+fn receive_body(stream: &mut TcpStream, file: &mut File, header: &Header) -> io::Result<()> {
+    let received = crate::recv_file(stream, file, header.length, RecvOptions::default())?;
+
+    if received != header.length {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            "stream ended before the entire file was received",
+        ));
+    };
+
+    if let Some(checksum) = header.checksum {
+        if crc32(file, header.length)? != checksum {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "checksum of the received file does not match",
+            ));
+        };
+    };
+
+    Ok(())
+}
+
+fn encode(header: &Header) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(32);
+
+    let mut flags = 0;
+
+    if header.name.is_some() {
+        flags |= FLAG_NAME;
+    };
+
+    if header.checksum.is_some() {
+        flags |= FLAG_CHECKSUM;
+    };
+
+    buf.extend_from_slice(MAGIC);
+    buf.push(VERSION);
+    buf.push(flags);
+    buf.extend_from_slice(&header.length.to_be_bytes());
+
+    if let Some(ref name) = header.name {
+        buf.extend_from_slice(&(name.len() as u16).to_be_bytes());
+        buf.extend_from_slice(name.as_bytes());
+    };
+
+    if let Some(checksum) = header.checksum {
+        buf.extend_from_slice(&checksum.to_be_bytes());
+    };
+
+    buf
+}
+
+fn decode<R: Read>(stream: &mut R) -> io::Result<Header> {
+    let mut fixed = [0; 14];
+    stream.read_exact(&mut fixed)?;
+
+    if &fixed[0..4] != MAGIC {
+        return Err(Error::new(ErrorKind::InvalidData, "invalid magic"));
+    };
+
+    if fixed[4] != VERSION {
+        return Err(Error::new(ErrorKind::InvalidData, "unsupported version"));
+    };
+
+    let flags = fixed[5];
+
+    if flags & !(FLAG_NAME | FLAG_CHECKSUM) != 0 {
+        return Err(Error::new(ErrorKind::InvalidData, "unknown flags"));
+    };
+
+    let mut length = [0; 8];
+    length.copy_from_slice(&fixed[6..14]);
+
+    let name = if flags & FLAG_NAME != 0 {
+        let mut name_length = [0; 2];
+        stream.read_exact(&mut name_length)?;
+
+        let mut name = vec![0; u16::from_be_bytes(name_length) as usize];
+        stream.read_exact(&mut name)?;
+
+        let name = String::from_utf8(name)
+            .map_err(|_| Error::new(ErrorKind::InvalidData, "name is not valid UTF-8"))?;
+        check_name(&name)?;
+
+        Some(name)
+    } else {
+        None
+    };
+
+    let checksum = if flags & FLAG_CHECKSUM != 0 {
+        let mut checksum = [0; 4];
+        stream.read_exact(&mut checksum)?;
+
+        Some(u32::from_be_bytes(checksum))
+    } else {
+        None
+    };
+
+    Ok(Header {
+        length: u64::from_be_bytes(length),
+        name,
+        checksum,
+    })
+}
+
+/// Checks that the name cannot refer to a file outside of the directory.
+///
+/// The name must consist of exactly one normal component, which excludes `.`, `..`, roots and prefixes like `C:`,
+/// and must not contain separators of other platforms either.
+fn check_name(name: &str) -> io::Result<()> {
+    let mut components = Path::new(name).components();
+
+    let single = match (components.next(), components.next()) {
+        (Some(Component::Normal(component)), None) => component == name,
+        _ => false,
+    };
+
+    if !single || name.len() > u16::MAX as usize || name.contains(['/', '\\', '\0']) {
+        return Err(Error::new(ErrorKind::InvalidData, "invalid file name"));
+    };
+
+    Ok(())
+}
+
+/// Returns a name which is unique for the process.
+fn unique_name() -> String {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.subsec_nanos())
+        .unwrap_or(0);
+
+    format!(
+        "received-{}-{}-{}",
+        process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed),
+        nanos
+    )
+}
+
+const CRC32_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut index = 0;
+
+    while index < 256 {
+        let mut crc = index as u32;
+        let mut bit = 0;
+
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                0xedb88320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+
+        table[index] = crc;
+        index += 1;
+    }
+
+    table
+};
+
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for &byte in data {
+        crc = CRC32_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8);
+    }
+
+    crc
+}
+
+/// Computes the checksum of the first `length` bytes of the file, without changing the file offset.
+fn crc32(file: &File, length: u64) -> io::Result<u32> {
+    let pool = crate::BufferPool::global();
+    let mut buf = pool.take();
+    let mut crc = !0;
+    let mut offset = 0;
+
+    while offset < length {
+        let chunk = (length - offset).min(buf.len() as u64) as usize;
+
+        match crate::fallback::read_at(file, &mut buf[..chunk], offset) {
+            Ok(0) => break, // the file has been truncated, which sending it reports
+            Ok(read) => {
+                crc = crc32_update(crc, &buf[..read]);
+                offset += read as u64;
+            }
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+    }
+
+    pool.give(buf);
+
+    Ok(!crc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum() {
+        assert_eq!(!crc32_update(!0, b"123456789"), 0xcbf43926);
+
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(b"123456789 and data appended later")
+            .unwrap();
+
+        assert_eq!(crc32(&file, 9).unwrap(), 0xcbf43926);
+    }
+
+    #[test]
+    fn names() {
+        assert!(check_name("artifact.tar").is_ok());
+
+        for name in &["", ".", "..", "a/b", "a\\b", "/a", "a/", "a\0"] {
+            assert!(check_name(name).is_err(), "{:?} was accepted", name);
+        }
+
+        #[cfg(windows)]
+        assert!(check_name("C:a").is_err());
+    }
+
+    #[test]
+    fn header() {
+        let header = Header {
+            length: 13,
+            name: Some(String::from("test_file")),
+            checksum: Some(0xcbf43926),
+        };
+
+        assert_eq!(decode(&mut &encode(&header)[..]).unwrap(), header);
+
+        let mut invalid = encode(&Header {
+            length: 13,
+            name: Some(String::from("xx")),
+            checksum: None,
+        });
+        let end = invalid.len();
+        invalid[end - 2..].copy_from_slice(b"..");
+
+        assert_eq!(
+            decode(&mut &invalid[..]).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+}
@@ -11,7 +11,8 @@ mod sendfile {
         stream: c_int,
         offset: off_t,
         nbytes: size_t,
-    ) -> Result<(), (Error, off_t)> {
+        flags: c_int,
+    ) -> Result<off_t, (Error, off_t)> {
         let mut sent = 0;
 
         if unsafe {
@@ -22,20 +23,23 @@ mod sendfile {
                 nbytes,
                 ptr::null_mut(),
                 &mut sent as *mut off_t,
-                0,
+                flags,
             )
         } == -1
         {
             Err((Error::last_os_error(), sent))
         } else {
-            Ok(())
+            Ok(sent)
         }
     }
 }
 
 use sendfile::*;
 
+use crate::cache;
 use crate::fallback;
+use crate::unsupported;
+use crate::NoDiskIo;
 
 use libc::{off_t, size_t};
 use std::fs::File;
@@ -45,28 +49,46 @@ use std::os::unix::io::AsRawFd;
 
 #[cfg(not(feature = "large-files"))]
 pub fn send_file(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
+    if unsupported::is_unsupported(file, stream) {
+        return fallback::send_file_from_start(file, stream);
+    };
+
     let mut offset: off_t = 0;
 
     loop {
         // loop until the file has been sent and handle WouldBlock and Interrupted errors
 
-        match try_sendfile(file.as_raw_fd(), stream.as_raw_fd(), offset, 0) {
+        match try_sendfile(file.as_raw_fd(), stream.as_raw_fd(), offset, 0, 0) {
+            Err((ref e, 0)) if offset == 0 && unsupported::check(e) => {
+                unsupported::mark_unsupported(file, stream);
+
+                return fallback::send_file_from_start(file, stream);
+            }
             Err((ref e, sent)) if check_error(e.kind()) => {
                 offset += sent;
             }
-            other => return other.map_err(|(e, _)| e),
+            other => return other.map(|_| ()).map_err(|(e, _)| e),
         };
     }
 }
 
 #[cfg(feature = "large-files")]
 pub fn send_file(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
+    if unsupported::is_unsupported(file, stream) {
+        return fallback::send_file_from_start(file, stream);
+    };
+
     let mut offset: off_t = 0;
 
     loop {
         // loop until the file has been sent and handle WouldBlock and Interrupted errors
 
-        match try_sendfile(file.as_raw_fd(), stream.as_raw_fd(), offset, 0) {
+        match try_sendfile(file.as_raw_fd(), stream.as_raw_fd(), offset, 0, 0) {
+            Err((ref e, 0)) if offset == 0 && unsupported::check(e) => {
+                unsupported::mark_unsupported(file, stream);
+
+                return fallback::send_file_from_start(file, stream);
+            }
             Err((ref e, sent)) if check_error(e.kind()) => {
                 let (new_offset, overflow) = offset.overflowing_add(sent);
 
@@ -79,20 +101,73 @@ pub fn send_file(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
                     offset = new_offset;
                 }
             }
-            other => return other.map_err(|(e, _)| e),
+            other => return other.map(|_| ()).map_err(|(e, _)| e),
         };
     }
 }
 
+/// Sends at most `length` bytes at `offset` using a single system call.
+///
+/// The amount of bytes sent is returned, which is `0` at the end of the file.
+/// Errors like `WouldBlock` are only returned if nothing has been sent.
+pub fn send_chunk(file: &File, stream: &TcpStream, offset: u64, length: u64) -> io::Result<u64> {
+    match try_send_chunk(file, stream, offset, length)? {
+        Some(sent) => Ok(sent),
+        None => fallback::send_chunk(file, stream, offset, length),
+    }
+}
+
+/// Like [`send_chunk()`], but returns `None` instead of falling back to reading the file
+/// if the native `sendfile()` does not support the file, the stream or the offset.
+///
+/// [`send_chunk()`]: fn.send_chunk.html
+pub fn try_send_chunk(
+    file: &File,
+    stream: &TcpStream,
+    offset: u64,
+    length: u64,
+) -> io::Result<Option<u64>> {
+    // a length of 0 would send the entire file
+    if length == 0 {
+        return Ok(Some(0));
+    };
+
+    if offset > off_t::MAX as u64 || unsupported::is_unsupported(file, stream) {
+        return Ok(None);
+    };
+
+    match try_sendfile(
+        file.as_raw_fd(),
+        stream.as_raw_fd(),
+        offset as off_t,
+        length.min(size_t::MAX as u64) as size_t,
+        0,
+    ) {
+        Ok(sent) => Ok(Some(sent as u64)),
+        Err((_, sent)) if sent > 0 => Ok(Some(sent as u64)),
+        Err((ref e, _)) if unsupported::check(e) => {
+            unsupported::mark_unsupported(file, stream);
+
+            Ok(None)
+        }
+        Err((e, _)) => Err(e),
+    }
+}
+
 #[inline]
 fn check_error(e: ErrorKind) -> bool {
     e == ErrorKind::WouldBlock || e == ErrorKind::Interrupted
 }
 
-pub fn send_exact(file: &File, stream: &TcpStream, length: u64, offset: u64) -> io::Result<u64> {
+pub fn send_exact(
+    file: &mut File,
+    stream: &mut TcpStream,
+    length: u64,
+    offset: u64,
+) -> io::Result<u64> {
     #[cfg(feature = "large-files")]
     {
-        if offset > off_t::max_value() as u64 {
+        if offset > off_t::MAX as u64 {
             return Err(Error::new(
                 ErrorKind::InvalidData,
                 "offset exceeds maximum size",
@@ -100,8 +175,51 @@ pub fn send_exact(file: &File, stream: &TcpStream, length: u64, offset: u64) ->
         };
     }
 
-    let length = if length > size_t::max_value() as u64 {
-        size_t::max_value()
+    if unsupported::is_unsupported(file, stream) {
+        return fallback::send_exact(file, stream, length, offset);
+    };
+
+    let length = if length > size_t::MAX as u64 {
+        size_t::MAX
+    } else {
+        length as size_t
+    };
+
+    match try_sendfile(
+        file.as_raw_fd(),
+        stream.as_raw_fd(),
+        offset as off_t,
+        length,
+        0,
+    ) {
+        Ok(sent) => Ok(sent as u64),
+        Err((ref e, 0)) if unsupported::check(e) => {
+            unsupported::mark_unsupported(file, stream);
+
+            fallback::send_exact(file, stream, length as u64, offset)
+        }
+        Err((e, _)) => Err(e),
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+pub fn send_exact_nodiskio(
+    file: &mut File,
+    stream: &mut TcpStream,
+    length: u64,
+    offset: u64,
+) -> io::Result<NoDiskIo> {
+    // a length of 0 would send the entire file
+    if length == 0 || offset > off_t::MAX as u64 {
+        return Ok(NoDiskIo::Sent(0));
+    };
+
+    if unsupported::is_unsupported(file, stream) {
+        return fallback::send_exact_nodiskio(file, stream, length, offset);
+    };
+
+    let length = if length > size_t::MAX as u64 {
+        size_t::MAX
     } else {
         length as size_t
     };
@@ -111,8 +229,31 @@ pub fn send_exact(file: &File, stream: &TcpStream, length: u64, offset: u64) ->
         stream.as_raw_fd(),
         offset as off_t,
         length,
+        libc::SF_NODISKIO,
     ) {
-        Ok(()) => Ok(length as u64),
+        Ok(sent) => Ok(NoDiskIo::Sent(sent as u64)),
+        Err((ref e, 0)) if e.raw_os_error() == Some(libc::EBUSY) => Ok(NoDiskIo::WouldBlockOnDisk),
+        Err((ref e, sent)) if e.raw_os_error() == Some(libc::EBUSY) || check_error(e.kind()) => {
+            Ok(NoDiskIo::Sent(sent as u64))
+        }
+        Err((ref e, 0)) if unsupported::check(e) => {
+            unsupported::mark_unsupported(file, stream);
+
+            fallback::send_exact_nodiskio(file, stream, length as u64, offset)
+        }
         Err((e, _)) => Err(e),
     }
 }
+
+#[cfg(target_os = "dragonfly")]
+pub fn send_exact_nodiskio(
+    file: &mut File,
+    stream: &mut TcpStream,
+    length: u64,
+    offset: u64,
+) -> io::Result<NoDiskIo> {
+    match cache::resident(file, offset, length)? {
+        0 if length != 0 => Ok(NoDiskIo::WouldBlockOnDisk),
+        resident => send_exact(file, stream, resident, offset).map(NoDiskIo::Sent),
+    }
+}
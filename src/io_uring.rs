@@ -0,0 +1,378 @@
+/*!
+Sending many files at once using [`io_uring`] on Linux.
+
+This module is only available on Linux with the `io-uring` feature.
+
+[`io_uring`]: https://man7.org/linux/man-pages/man7/io_uring.7.html
+*/
+
+use ::io_uring::{opcode, types, IoUring, Probe};
+
+use crate::pipe::Pipe;
+use crate::{unsupported, wait};
+
+use std::fs::File;
+use std::io::{self, Error, ErrorKind};
+use std::net::TcpStream;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+/// The maximum amount of bytes moved by one `splice()` operation,
+/// which is the default capacity of a pipe.
+const CHUNK: u64 = 64 * 1024;
+
+/// The kinds of operations, which are stored in the lowest bits of their user data.
+const FROM_FILE: u64 = 0;
+const TO_STREAM: u64 = 1;
+const POLL_STREAM: u64 = 2;
+
+/// An `io_uring` instance which sends batches of files to TCP streams.
+///
+/// Every file is spliced into a pipe and from there into its stream using `IORING_OP_SPLICE`,
+/// so that the data never needs to be copied into userspace.
+/// All transfers of a batch progress concurrently, and their completions are collected from the same ring.
+/// A non-blocking stream which is not writable is polled using `IORING_OP_POLL_ADD` before the next splice into it.
+///
+/// If the kernel does not support `io_uring` or the `IORING_OP_SPLICE` operation,
+/// or it is disabled, for example by a seccomp filter,
+/// the transfers are sent one after another using the regular `sendfile()` implementation instead.
+///
+/// # Example
+///
+/// ```
+/// use snedfile::io_uring::Ring;
+/// # use std::io;
+/// # use std::fs::File;
+/// # use std::net::TcpStream;
+///
+/// fn serve_all(transfers: &[(&File, &TcpStream)]) -> io::Result<()> {
+///     let mut ring = Ring::new(64)?;
+///
+///     for result in ring.send_files(transfers)? {
+///         if let Err(e) = result {
+///             eprintln!("transfer failed: {}", e);
+///         };
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+pub struct Ring {
+    ring: Option<IoUring>,
+}
+
+impl Ring {
+    /// Creates a ring with room for `entries` concurrent operations,
+    /// which is also the maximum amount of transfers progressing at the same time.
+    ///
+    /// If `io_uring` is unsupported, this succeeds nevertheless and the ring uses the fallback.
+    pub fn new(entries: u32) -> io::Result<Ring> {
+        let ring = match IoUring::new(entries) {
+            Ok(ring) => ring,
+            Err(ref e) if unsupported::check(e) || e.raw_os_error() == Some(libc::EPERM) => {
+                return Ok(Ring { ring: None });
+            }
+            Err(e) => return Err(e),
+        };
+
+        let mut probe = Probe::new();
+        let supported = ring.submitter().register_probe(&mut probe).is_ok()
+            && probe.is_supported(opcode::Splice::CODE);
+
+        Ok(Ring {
+            ring: if supported { Some(ring) } else { None },
+        })
+    }
+
+    /// Returns `true` if the transfers are actually sent using `io_uring`,
+    /// and `false` if the fallback is used.
+    #[inline]
+    pub fn is_supported(&self) -> bool {
+        self.ring.is_some()
+    }
+
+    /// Sends the entire contents of every file to its stream, and blocks until all transfers are complete.
+    ///
+    /// The files are always sent starting at offset `0`, and the file offsets are not changed.
+    ///
+    /// The returned vector contains the result of every transfer in the same order,
+    /// which is the amount of bytes sent if successful.
+    /// The outer error is only returned if the ring itself fails,
+    /// in which case the state of the transfers is unknown.
+    /// The operations still running are waited for before the error is returned,
+    /// and if that fails too, the ring is discarded and later calls use the fallback.
+    pub fn send_files(
+        &mut self,
+        transfers: &[(&File, &TcpStream)],
+    ) -> io::Result<Vec<io::Result<u64>>> {
+        match self.ring {
+            Some(ref mut ring) => {
+                let mut states: Vec<State> = transfers
+                    .iter()
+                    .map(|&(file, _)| State::new(file))
+                    .collect();
+                let mut in_flight = 0;
+
+                // the pipes are only closed once the operations using them have completed
+                if let Err(e) = run(ring, transfers, &mut states, &mut in_flight) {
+                    if drain(ring, in_flight).is_err() {
+                        // stale completions would be mistaken for those of the next batch
+                        self.ring = None;
+                    };
+
+                    return Err(e);
+                };
+
+                Ok(states
+                    .into_iter()
+                    .map(|state| state.result.unwrap_or(Ok(0)))
+                    .collect())
+            }
+            None => Ok(transfers
+                .iter()
+                .map(|&(file, stream)| send_blocking(file, stream))
+                .collect()),
+        }
+    }
+}
+
+/// The state of one transfer of a batch.
+struct State {
+    length: u64,
+    /// The amount of bytes spliced from the file into the pipe.
+    read: u64,
+    /// The amount of bytes spliced from the pipe into the stream.
+    sent: u64,
+    pipe: Option<Pipe>,
+    pending: bool,
+    /// The stream was not writable, so it is polled before splicing into it again.
+    blocked: bool,
+    result: Option<io::Result<u64>>,
+}
+
+impl State {
+    fn new(file: &File) -> State {
+        let (length, result) = match file.metadata() {
+            Ok(metadata) => (metadata.len(), None),
+            Err(e) => (0, Some(Err(e))),
+        };
+
+        State {
+            length,
+            read: 0,
+            sent: 0,
+            pipe: None,
+            pending: false,
+            blocked: false,
+            result,
+        }
+    }
+}
+
+/// Runs a batch of transfers, counting the operations submitted but not completed in `in_flight`.
+fn run(
+    ring: &mut IoUring,
+    transfers: &[(&File, &TcpStream)],
+    states: &mut [State],
+    in_flight: &mut usize,
+) -> io::Result<()> {
+    let capacity = ring.params().sq_entries() as usize;
+
+    loop {
+        // submit the next operation of every transfer which has none running
+        for (index, state) in states.iter_mut().enumerate() {
+            if *in_flight == capacity {
+                break;
+            };
+
+            if state.pending || state.result.is_some() {
+                continue;
+            };
+
+            let (file, stream) = transfers[index];
+
+            let entry = match next_entry(index, state, file.as_raw_fd(), stream.as_raw_fd()) {
+                Ok(Some(entry)) => entry,
+                Ok(None) => {
+                    state.pipe = None;
+                    state.result = Some(Ok(state.sent));
+                    continue;
+                }
+                Err(e) => {
+                    state.pipe = None;
+                    state.result = Some(Err(e));
+                    continue;
+                }
+            };
+
+            unsafe { ring.submission().push(&entry) }
+                .map_err(|_| Error::other("submission queue is full"))?;
+
+            state.pending = true;
+            *in_flight += 1;
+        }
+
+        if *in_flight == 0 {
+            break;
+        };
+
+        match ring.submit_and_wait(1) {
+            Ok(_) => (),
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        for completion in ring.completion() {
+            let index = (completion.user_data() >> 2) as usize;
+            let kind = completion.user_data() & 3;
+            let state = &mut states[index];
+
+            state.pending = false;
+            *in_flight -= 1;
+
+            if kind == POLL_STREAM {
+                // an error of the stream is reported by the next splice
+                state.blocked = false;
+                continue;
+            };
+
+            let to_stream = kind == TO_STREAM;
+
+            match completion.result() {
+                result if result < 0 => {
+                    let e = Error::from_raw_os_error(-result);
+
+                    // a non-blocking stream is polled instead of retrying the splice right away
+                    if to_stream && e.kind() == ErrorKind::WouldBlock {
+                        state.blocked = true;
+                        continue;
+                    };
+
+                    if check_error(e.kind()) {
+                        continue;
+                    };
+
+                    state.pipe = None;
+                    state.result = Some(if state.read == 0 && unsupported::check(&e) {
+                        let (file, stream) = transfers[index];
+
+                        send_blocking(file, stream)
+                    } else {
+                        Err(e)
+                    });
+                }
+                // the file has been truncated
+                0 if !to_stream => state.length = state.read,
+                0 => {
+                    state.pipe = None;
+                    state.result = Some(Err(Error::from(ErrorKind::WriteZero)));
+                }
+                result if to_stream => state.sent += result as u64,
+                result => state.read += result as u64,
+            };
+        }
+    }
+
+    Ok(())
+}
+
+/// Waits for the completion of the operations still running after an error, and discards them.
+fn drain(ring: &mut IoUring, mut in_flight: usize) -> io::Result<()> {
+    while in_flight > 0 {
+        match ring.submit_and_wait(1) {
+            Ok(_) => (),
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        in_flight -= ring.completion().count().min(in_flight);
+    }
+
+    Ok(())
+}
+
+/// Returns the next operation of a transfer, or `None` if it is complete.
+fn next_entry(
+    index: usize,
+    state: &mut State,
+    file: RawFd,
+    stream: RawFd,
+) -> io::Result<Option<::io_uring::squeue::Entry>> {
+    let user_data = (index as u64) << 2;
+
+    if state.blocked {
+        let entry = opcode::PollAdd::new(types::Fd(stream), libc::POLLOUT as u32)
+            .build()
+            .user_data(user_data | POLL_STREAM);
+
+        return Ok(Some(entry));
+    };
+
+    if state.sent < state.read {
+        let pipe = state.pipe.as_ref().expect("pipe with data");
+
+        let entry = opcode::Splice::new(
+            types::Fd(pipe.read),
+            -1,
+            types::Fd(stream),
+            -1,
+            (state.read - state.sent) as u32,
+        )
+        .build()
+        .user_data(user_data | TO_STREAM);
+
+        return Ok(Some(entry));
+    };
+
+    if state.read >= state.length {
+        return Ok(None);
+    };
+
+    if state.read > i64::MAX as u64 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "offset exceeds maximum size",
+        ));
+    };
+
+    if state.pipe.is_none() {
+        state.pipe = Some(Pipe::new()?);
+    };
+
+    let pipe = state.pipe.as_ref().expect("pipe");
+
+    let entry = opcode::Splice::new(
+        types::Fd(file),
+        state.read as i64,
+        types::Fd(pipe.write),
+        -1,
+        (state.length - state.read).min(CHUNK) as u32,
+    )
+    .build()
+    .user_data(user_data | FROM_FILE);
+
+    Ok(Some(entry))
+}
+
+/// Sends the entire file without `io_uring`.
+fn send_blocking(file: &File, stream: &TcpStream) -> io::Result<u64> {
+    let length = file.metadata()?.len();
+
+    let mut offset = 0;
+
+    while offset < length {
+        match crate::imp::send_chunk(file, stream, offset, length - offset) {
+            Ok(0) => break, // the file has been truncated
+            Ok(sent) => offset += sent,
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => wait::writable(stream)?,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+    }
+
+    Ok(offset)
+}
+
+#[inline]
+fn check_error(e: ErrorKind) -> bool {
+    e == ErrorKind::WouldBlock || e == ErrorKind::Interrupted
+}
@@ -9,6 +9,12 @@ All native implementation support a maximum file length of [`off_t::max_value()`
 
 All implementations handle `WouldBlock` and `Interrupted` errors.
 
+Some file systems and socket types are not supported by `sendfile()`,
+in which case it fails with `EINVAL`, `ENOSYS` or `EOPNOTSUPP` before sending anything.
+The native implementations then transparently use the fallback instead,
+and remember the file and stream so later calls on them skip the failing system call.
+Only the most recent 64 pairs are remembered, and until one has been recorded, no system calls or locks are used for checking them.
+
 ## Linux and android
 
 The [`sendfile(2)`][linux] system call is used.
@@ -32,18 +38,31 @@ The file length is not required.
 
 ## Fallback
 
-There are two features to change the fallback behavior:
+There are four features to change the fallback behavior:
 
 The `fallback-bufreader` feature is enabled by default.
 It sends the file using [`io::copy()`] after wrapping it in a [`BufReader`].
 `Interrupted` errors are handled by the implementation.
 
 If the `fallback-buf` feature is enabled,
-the entire contents of the file are loaded into a `Vec` first,
-which is then written to the stream at once.
-Only the first `usize::max_value()` may be transmitted.
+the file is streamed through fixed-size buffers taken from the global [`BufferPool`],
+so the memory usage does not depend on the file size.
+The buffer size can be changed using [`BufferPool::set_buffer_size()`],
+and [`send_file_with_pool()`] uses a pool supplied by the caller instead.
+
+If the `fallback-mmap` feature is enabled on a Unix platform,
+the file is mapped into memory in windows of 64 megabytes,
+which are written to the stream without copying them into a userspace buffer first.
+If the file is truncated during the transfer, the rest is sent by reading it instead.
+[`send_file_mmap()`] does the same with a custom window size.
+
+If the `fallback-readahead` feature is enabled,
+a helper thread reads the next buffer from the file while the current one is written to the stream,
+so that slow disk reads and slow network writes overlap.
+The two buffers are taken from the global [`BufferPool`].
+This also applies to the bytes sent by the fallback after the native limit when using the `large-files` feature.
 
-If both features are disabled the file is transmitted by repeatedly using bare [`io::copy()`] until all bytes have been sent.
+If all features are disabled the file is transmitted by repeatedly using bare [`io::copy()`] until all bytes have been sent.
 
 # Large files
 
@@ -52,6 +71,52 @@ files larger than 8192 *peta*bytes from a 64-bit system,
 enable the `large-files` feature which supports all file sizes up to `u64::max_value()`,
 and if the files become to large for the native solutions a fallback is used.
 
+# Zero-copy sends from memory
+
+On Linux, [`zerocopy::ZeroCopySender`] sends buffers in memory, like memory-mapped files,
+using `MSG_ZEROCOPY` instead of copying them into the kernel.
+
+# Splicing
+
+On Linux, the [`splice`] module moves data through a pipe using `splice()` instead of `sendfile()`,
+which also works for sources and sinks `sendfile()` does not support, like pipes.
+
+# Framed transfers
+
+The [`framed`] module contains a small protocol for sending a file along with its length, name and checksum,
+and receiving it on the other side.
+
+Multiple files can be sent as a tar archive using [`tar::TarWriter`].
+
+# Resumable transfers
+
+[`resume::send_resumable()`] reports its progress periodically,
+so that an interrupted transfer of a large file can continue where it stopped
+instead of starting over.
+
+# Cancellation and timeouts
+
+[`send_file_cancellable()`] stops a transfer once its [`CancelToken`] is cancelled from another thread,
+which also ends the log streaming below.
+[`send_file_with_timeouts()`] gives up after a deadline, or if the peer stops receiving for too long.
+[`send_file_with_options()`] combines both with a progress callback.
+
+# Log streaming
+
+[`follow()`] keeps sending what is appended to a file, like `tail -f`.
+[`ship::ship_log()`] does the same for a path, following the log across rotations
+and storing its position so that it can resume after a restart.
+
+# Asynchronous I/O
+
+If the `tokio` feature is enabled, [`tokio::send_file()`] sends files to `tokio` TCP streams,
+waiting for the stream to become writable instead of blocking the runtime.
+Likewise, the `async-io` feature enables [`async_io::send_file()`] for `async-io` TCP and Unix streams.
+For event loops built directly on `mio`, the `mio` feature provides the resumable [`mio::Transfer`].
+
+On Linux, the `io-uring` feature enables [`io_uring::Ring`],
+which sends many files at once using `io_uring` instead of one blocking system call after another.
+
 [linux]: http://man7.org/linux/man-pages/man2/sendfile.2.html
 [macos]: https://developer.apple.com/library/archive/documentation/System/Conceptual/ManPages_iPhoneOS/man2/sendfile.2.html
 [bsd]: https://www.freebsd.org/cgi/man.cgi?query=sendfile
@@ -59,6 +124,25 @@ and if the files become to large for the native solutions a fallback is used.
 [`off_t::max_value()`]: https://docs.rs/libc/0.2/libc/type.off_t.html
 [`io::copy()`]: https://doc.rust-lang.org/stable/std/io/fn.copy.html
 [`BufReader`]: https://doc.rust-lang.org/stable/std/io/struct.BufReader.html
+[`BufferPool`]: struct.BufferPool.html
+[`BufferPool::set_buffer_size()`]: struct.BufferPool.html#method.set_buffer_size
+[`send_file_with_pool()`]: fn.send_file_with_pool.html
+[`send_file_mmap()`]: fn.send_file_mmap.html
+[`follow()`]: fn.follow.html
+[`send_file_cancellable()`]: fn.send_file_cancellable.html
+[`CancelToken`]: struct.CancelToken.html
+[`send_file_with_timeouts()`]: fn.send_file_with_timeouts.html
+[`send_file_with_options()`]: fn.send_file_with_options.html
+[`framed`]: framed/index.html
+[`resume::send_resumable()`]: resume/fn.send_resumable.html
+[`ship::ship_log()`]: ship/fn.ship_log.html
+[`splice`]: splice/index.html
+[`tar::TarWriter`]: tar/struct.TarWriter.html
+[`zerocopy::ZeroCopySender`]: zerocopy/struct.ZeroCopySender.html
+[`tokio::send_file()`]: tokio/fn.send_file.html
+[`async_io::send_file()`]: async_io/fn.send_file.html
+[`mio::Transfer`]: mio/struct.Transfer.html
+[`io_uring::Ring`]: io_uring/struct.Ring.html
 */
 
 #![deny(missing_docs)]
@@ -81,7 +165,51 @@ mod imp;
 #[path = "macos.rs"]
 mod imp;
 
+#[cfg(feature = "async-io")]
+pub mod async_io;
+mod batch;
+mod broadcast;
+mod cache;
+mod cancel;
+mod checkpoint;
+mod copy;
 mod fallback;
+mod follow;
+pub mod framed;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+pub mod io_uring;
+#[cfg(feature = "mio")]
+pub mod mio;
+#[cfg(unix)]
+mod mmap;
+#[cfg(target_os = "linux")]
+mod pipe;
+mod pool;
+mod progress;
+mod proxy;
+mod recv;
+pub mod resume;
+pub mod ship;
+#[cfg(target_os = "linux")]
+pub mod splice;
+pub mod tar;
+mod timeout;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+mod transfer;
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    all(target_os = "ios", feature = "ios-sendfile"),
+    target_os = "freebsd",
+    target_os = "dragonfly"
+))]
+mod unsupported;
+mod wait;
+#[cfg(target_os = "linux")]
+pub mod zerocopy;
 
 #[cfg(not(any(
     target_os = "linux",
@@ -93,12 +221,37 @@ mod fallback;
 )))]
 use fallback as imp;
 
-#[cfg(all(feature = "fallback-bufreader", feature = "fallback-buf"))]
+#[cfg(any(
+    all(
+        feature = "fallback-bufreader",
+        any(
+            feature = "fallback-buf",
+            feature = "fallback-mmap",
+            feature = "fallback-readahead"
+        )
+    ),
+    all(
+        feature = "fallback-buf",
+        any(feature = "fallback-mmap", feature = "fallback-readahead")
+    ),
+    all(feature = "fallback-mmap", feature = "fallback-readahead")
+))]
 compile_error!("Only one `fallback-*` feature can enabled");
 
+pub use batch::SendFilesError;
+pub use cache::{CachePolicy, NoDiskIo};
+pub use cancel::{CancelToken, Cancelled};
+pub use copy::CopyMethod;
+pub use pool::BufferPool;
+pub use progress::{Aborted, Control, Granularity};
+pub use recv::RecvOptions;
+pub use timeout::{TimedOut, Timeouts};
+pub use transfer::SendOptions;
+
 use std::fs::File;
 use std::io;
 use std::net::TcpStream;
+use std::ops::RangeBounds;
 
 /// Sends the entire contents of a file to a TCP stream.
 ///
@@ -134,6 +287,55 @@ pub fn send_file(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
     imp::send_file(file, stream)
 }
 
+/// Sends the entire contents of a file to a TCP stream with hints about the page cache usage.
+///
+/// This behaves like [`send_file()`], but gives the hints of `policy` to the kernel before and after the transfer.
+/// See [`CachePolicy`] for the available policies.
+///
+/// # Example
+///
+/// ```
+/// use snedfile::{send_file_with_policy, CachePolicy};
+/// # use std::io;
+/// # use std::fs::File;
+/// # use std::net::TcpStream;
+///
+/// // large downloads should not evict frequently used files from the page cache
+/// fn serve_download(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
+///     send_file_with_policy(file, stream, CachePolicy::NoReuse)
+/// }
+/// ```
+///
+/// [`send_file()`]: fn.send_file.html
+/// [`CachePolicy`]: enum.CachePolicy.html
+pub fn send_file_with_policy(
+    file: &mut File,
+    stream: &mut TcpStream,
+    policy: CachePolicy,
+) -> io::Result<()> {
+    let length = if policy == CachePolicy::Prefetch {
+        file.metadata()?.len()
+    } else {
+        0
+    };
+
+    cache::before(file, length, policy);
+    let result = imp::send_file(file, stream);
+    cache::after(file, policy);
+
+    result
+}
+
+/// Reads a part of a file into the page cache, so that sending it later does not block on the disk.
+///
+/// On Linux `readahead(2)` is used, and on other platforms the kernel is advised to start reading, if supported.
+/// Either may return before the data has been read,
+/// and `Ok(())` is returned if the platform has no such mechanism.
+#[inline]
+pub fn prefetch(file: &File, offset: u64, length: u64) -> io::Result<()> {
+    cache::prefetch(file, offset, length)
+}
+
 /// Send a specific amount of bytes from a specific offset within a file.
 ///
 /// The amount of bytes successfully sent is returned.
@@ -177,3 +379,603 @@ pub fn send_exact(
 ) -> io::Result<u64> {
     imp::send_exact(file, stream, bytes, offset)
 }
+
+/// Sends the entire contents of a file to a TCP stream, reporting the progress to a callback.
+///
+/// The callback receives the amount of bytes sent so far and the total length of the file,
+/// and is invoked as often as `granularity` says, as well as once after the entire file has been sent.
+/// If it returns [`Control::Abort`], the transfer stops with an [`Aborted`] error.
+///
+/// The file is always sent starting at offset `0`, and the amount of bytes sent is returned,
+/// which is less than the length only if the file has been truncated during the transfer.
+///
+/// # Implementation notes
+///
+/// This is [`send_file_with_options()`] with only a progress callback.
+/// The file is sent using one system call after another, like [`send_file()`] does,
+/// and the callback is invoked between them.
+/// Every system call sends at most one megabyte, so [`Granularity::Chunk`] invokes the callback at least that often,
+/// while [`Granularity::Bytes`] counts the bytes sent by the system calls until it has been reached,
+/// and is only as precise as one system call.
+///
+/// # Example
+///
+/// ```
+/// use snedfile::{send_file_with_progress, Control, Granularity};
+/// # use std::io;
+/// # use std::fs::File;
+/// # use std::net::TcpStream;
+///
+/// fn download(file: &File, stream: &mut TcpStream) -> io::Result<u64> {
+///     // report the progress every megabyte
+///     send_file_with_progress(file, stream, Granularity::Bytes(1024 * 1024), |sent, total| {
+///         if let Some(total) = total {
+///             println!("{}%", sent * 100 / total.max(1));
+///         };
+///
+///         Control::Continue
+///     })
+/// }
+/// ```
+///
+/// [`Granularity::Chunk`]: enum.Granularity.html#variant.Chunk
+/// [`Granularity::Bytes`]: enum.Granularity.html#variant.Bytes
+/// [`Control::Abort`]: enum.Control.html#variant.Abort
+/// [`Aborted`]: struct.Aborted.html
+/// [`send_file()`]: fn.send_file.html
+/// [`send_file_with_options()`]: fn.send_file_with_options.html
+#[inline]
+pub fn send_file_with_progress<F>(
+    file: &File,
+    stream: &mut TcpStream,
+    granularity: Granularity,
+    mut progress: F,
+) -> io::Result<u64>
+where
+    F: FnMut(u64, Option<u64>) -> Control,
+{
+    let options = SendOptions {
+        granularity,
+        progress: Some(&mut progress),
+        ..SendOptions::default()
+    };
+
+    transfer::send_file(file, stream, options)
+}
+
+/// Sends the entire contents of a file to a TCP stream until the transfer is cancelled.
+///
+/// If `cancel` is cancelled before the entire file has been sent,
+/// the transfer stops with a [`Cancelled`] error containing the amount of bytes sent.
+///
+/// The file is always sent starting at offset `0`, and the amount of bytes sent is returned,
+/// which is less than the length only if the file has been truncated during the transfer.
+///
+/// # Implementation notes
+///
+/// This is [`send_file_with_options()`] with only a token.
+/// The token is checked before every system call, each of which sends at most one megabyte.
+/// Waiting for a non-blocking stream to become writable is interrupted by the cancellation,
+/// but a system call on a blocking stream cannot be, so a stalled peer may delay it indefinitely.
+///
+/// # Example
+///
+/// ```
+/// use snedfile::{send_file_cancellable, CancelToken, Cancelled};
+/// # use std::io;
+/// # use std::fs::File;
+/// # use std::net::TcpStream;
+///
+/// fn serve(file: &File, stream: &mut TcpStream, shutdown: &CancelToken) -> io::Result<()> {
+///     match send_file_cancellable(file, stream, shutdown) {
+///         Ok(_) => Ok(()),
+///         Err(e) => match e.get_ref().and_then(|e| e.downcast_ref::<Cancelled>()) {
+///             Some(cancelled) => {
+///                 println!("shutting down after {} bytes", cancelled.sent);
+///                 Ok(())
+///             }
+///             None => Err(e),
+///         },
+///     }
+/// }
+/// ```
+///
+/// [`Cancelled`]: struct.Cancelled.html
+/// [`send_file_with_options()`]: fn.send_file_with_options.html
+#[inline]
+pub fn send_file_cancellable(
+    file: &File,
+    stream: &mut TcpStream,
+    cancel: &CancelToken,
+) -> io::Result<u64> {
+    let options = SendOptions {
+        cancel: Some(cancel),
+        ..SendOptions::default()
+    };
+
+    transfer::send_file(file, stream, options)
+}
+
+/// Sends the entire contents of a file to a TCP stream within time limits.
+///
+/// If the deadline of `timeouts` passes before the entire file has been sent,
+/// or no bytes could be sent for longer than its idle timeout,
+/// an error of kind `TimedOut` is returned, which contains a [`TimedOut`] with the amount of bytes sent.
+///
+/// The file is always sent starting at offset `0`, and the amount of bytes sent is returned,
+/// which is less than the length only if the file has been truncated during the transfer.
+///
+/// # Implementation notes
+///
+/// This is [`send_file_with_options()`] with only timeouts.
+/// Waiting for a non-blocking stream to become writable is limited by the timeouts.
+/// For a blocking stream, the write timeout of the stream is set before every system call
+/// and restored afterwards, so that a stalled peer cannot block the transfer beyond the timeouts.
+///
+/// # Example
+///
+/// ```
+/// use snedfile::{send_file_with_timeouts, Timeouts};
+/// # use std::io;
+/// # use std::fs::File;
+/// # use std::net::TcpStream;
+/// use std::time::Duration;
+///
+/// fn serve(file: &File, stream: &mut TcpStream) -> io::Result<u64> {
+///     // give up after ten minutes, or if the client stops reading for 30 seconds
+///     let timeouts = Timeouts {
+///         idle: Some(Duration::from_secs(30)),
+///         ..Timeouts::total(Duration::from_secs(600))
+///     };
+///
+///     send_file_with_timeouts(file, stream, timeouts)
+/// }
+/// ```
+///
+/// [`TimedOut`]: struct.TimedOut.html
+/// [`send_file_with_options()`]: fn.send_file_with_options.html
+#[inline]
+pub fn send_file_with_timeouts(
+    file: &File,
+    stream: &mut TcpStream,
+    timeouts: Timeouts,
+) -> io::Result<u64> {
+    let options = SendOptions {
+        timeouts,
+        ..SendOptions::default()
+    };
+
+    transfer::send_file(file, stream, options)
+}
+
+/// Sends the entire contents of a file to a TCP stream with any combination of
+/// a [`CancelToken`], [`Timeouts`] and a progress callback.
+///
+/// The transfer stops with the error of whichever option stops it first,
+/// which is a [`Cancelled`], a [`TimedOut`] or an [`Aborted`] error,
+/// and each of them behaves like in [`send_file_cancellable()`], [`send_file_with_timeouts()`]
+/// and [`send_file_with_progress()`].
+///
+/// The file is always sent starting at offset `0`, and the amount of bytes sent is returned,
+/// which is less than the length only if the file has been truncated during the transfer.
+///
+/// # Implementation notes
+///
+/// If a token or a callback is given, every system call sends at most one megabyte,
+/// so that the token is checked and the callback is invoked often enough.
+/// Otherwise the file is sent in as few system calls as possible, like [`send_file()`] does.
+///
+/// # Example
+///
+/// ```
+/// use snedfile::{send_file_with_options, CancelToken, SendOptions, Timeouts};
+/// # use std::io;
+/// # use std::fs::File;
+/// # use std::net::TcpStream;
+/// use std::time::Duration;
+///
+/// fn serve(file: &File, stream: &mut TcpStream, shutdown: &CancelToken) -> io::Result<u64> {
+///     // stop when shutting down, or if the client stops reading for 30 seconds
+///     let options = SendOptions {
+///         cancel: Some(shutdown),
+///         timeouts: Timeouts {
+///             idle: Some(Duration::from_secs(30)),
+///             deadline: None,
+///         },
+///         ..SendOptions::default()
+///     };
+///
+///     send_file_with_options(file, stream, options)
+/// }
+/// ```
+///
+/// [`CancelToken`]: struct.CancelToken.html
+/// [`Timeouts`]: struct.Timeouts.html
+/// [`Cancelled`]: struct.Cancelled.html
+/// [`TimedOut`]: struct.TimedOut.html
+/// [`Aborted`]: struct.Aborted.html
+/// [`send_file_cancellable()`]: fn.send_file_cancellable.html
+/// [`send_file_with_timeouts()`]: fn.send_file_with_timeouts.html
+/// [`send_file_with_progress()`]: fn.send_file_with_progress.html
+/// [`send_file()`]: fn.send_file.html
+#[inline]
+pub fn send_file_with_options(
+    file: &File,
+    stream: &mut TcpStream,
+    options: SendOptions,
+) -> io::Result<u64> {
+    transfer::send_file(file, stream, options)
+}
+
+/// Sends the entire contents of a file to a TCP stream using buffers from a pool.
+///
+/// Unlike [`send_file()`], this always copies the file through userspace,
+/// regardless of the platform and the enabled features.
+/// At most one buffer of the pool is used at a time.
+///
+/// The file is read from the current file offset.
+///
+/// [`send_file()`]: fn.send_file.html
+#[inline]
+pub fn send_file_with_pool(
+    file: &mut File,
+    stream: &mut TcpStream,
+    pool: &BufferPool,
+) -> io::Result<()> {
+    pool.copy(file, stream)?;
+
+    Ok(())
+}
+
+/// Sends the entire contents of a file to a TCP stream by mapping it into memory.
+///
+/// The file is mapped in windows of `window` bytes, rounded up to the page size,
+/// and every window is written to the stream without copying it into a userspace buffer.
+/// A smaller window limits the amount of address space in use, which matters for huge files on 32-bit systems.
+///
+/// If the file is truncated during the transfer,
+/// the remaining bytes are sent by reading the file instead of failing with `SIGBUS`.
+///
+/// This function is only available on Unix platforms.
+///
+/// # Example
+///
+/// ```
+/// use snedfile::send_file_mmap;
+/// # use std::io;
+/// # use std::fs::File;
+/// # use std::net::TcpStream;
+///
+/// fn serve_static(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
+///     // map at most 16 megabytes at once
+///     send_file_mmap(file, stream, 16 * 1024 * 1024)
+/// }
+/// ```
+#[cfg(unix)]
+#[inline]
+pub fn send_file_mmap(file: &mut File, stream: &mut TcpStream, window: usize) -> io::Result<()> {
+    mmap::send_file(file, stream, window)
+}
+
+/// Send a specific amount of bytes from a specific offset within a file,
+/// but only the ones which can be sent without blocking on disk I/O.
+///
+/// This is meant for event loops, where waiting for the disk stalls every connection handled by the thread.
+/// If the bytes at `offset` are not in the page cache, nothing is sent and `NoDiskIo::WouldBlockOnDisk` is returned,
+/// so that the caller can send them from another thread or [`prefetch()`] them first.
+/// Otherwise the cached bytes directly following `offset` are sent like using [`send_exact()`],
+/// and their amount is returned.
+///
+/// # Implementation notes
+///
+/// On FreeBSD, the `SF_NODISKIO` flag of `sendfile()` is used.
+///
+/// On Linux, android, MacOS, iOS, DragonFlyBSD, NetBSD and OpenBSD
+/// the page cache residency is checked using `mincore()` before sending, for at most 16 megabytes at once.
+/// Every call maps the checked range into memory and unmaps it again, and queries the file length,
+/// which costs several system calls and is only worth it if the disk is slow compared to them.
+/// Note that pages may be evicted between the check and the transfer.
+///
+/// On other platforms, the bytes are always sent.
+///
+/// # Example
+///
+/// ```
+/// use snedfile::{prefetch, send_exact_nodiskio, NoDiskIo};
+/// # use std::io;
+/// # use std::fs::File;
+/// # use std::net::TcpStream;
+///
+/// fn try_serve(file: &mut File, stream: &mut TcpStream, offset: u64) -> io::Result<u64> {
+///     let len = file.metadata()?.len() - offset;
+///
+///     match send_exact_nodiskio(file, stream, len, offset)? {
+///         NoDiskIo::Sent(sent) => Ok(sent),
+///         NoDiskIo::WouldBlockOnDisk => {
+///             // in practice, this would be offloaded to a thread pool
+///             prefetch(file, offset, len)?;
+///             Ok(0)
+///         }
+///     }
+/// }
+/// ```
+///
+/// [`prefetch()`]: fn.prefetch.html
+/// [`send_exact()`]: fn.send_exact.html
+#[inline]
+pub fn send_exact_nodiskio(
+    file: &mut File,
+    stream: &mut TcpStream,
+    bytes: u64,
+    offset: u64,
+) -> io::Result<NoDiskIo> {
+    imp::send_exact_nodiskio(file, stream, bytes, offset)
+}
+
+/// Relays data between two TCP streams in both directions until both reach the end.
+///
+/// When one stream reaches the end, the writing half of the other one is shut down,
+/// so that half-closed connections are relayed correctly.
+/// The amounts of bytes moved from `a` to `b` and from `b` to `a` are returned.
+///
+/// One direction is handled by a separate thread, and the streams need to be in blocking mode.
+/// If either direction fails, both streams are shut down entirely and the error is returned.
+///
+/// # Implementation notes
+///
+/// Every call spawns a scoped thread, which exits once its direction has reached the end,
+/// so relaying many short-lived connections costs a thread creation each.
+///
+/// On Linux, the data is moved using `splice()` through a pipe, without copying it into userspace.
+/// Both pipes are taken from a pool of the calling thread and returned to it afterwards,
+/// so repeated calls on the same thread reuse them.
+/// On other platforms, it is copied using buffers from the global [`BufferPool`].
+///
+/// # Example
+///
+/// ```
+/// use snedfile::proxy;
+/// # use std::io;
+/// use std::net::TcpStream;
+///
+/// fn relay(client: &TcpStream) -> io::Result<()> {
+///     let upstream = TcpStream::connect("127.0.0.1:8080")?;
+///     let (sent, received) = proxy(client, &upstream)?;
+///
+///     println!("{} bytes sent, {} bytes received", sent, received);
+///     Ok(())
+/// }
+/// ```
+///
+/// [`BufferPool`]: struct.BufferPool.html
+#[inline]
+pub fn proxy(a: &TcpStream, b: &TcpStream) -> io::Result<(u64, u64)> {
+    proxy::proxy(a, b)
+}
+
+/// Sends the entire contents of a file to many TCP streams at once.
+///
+/// The returned vector contains the result of every stream in the same order,
+/// which is the amount of bytes sent if successful.
+/// A stream which fails is removed from the transfer, while the others continue.
+/// The outer error is only returned if the transfer itself fails.
+///
+/// The file is always sent starting at offset `0`, and the file offset is not changed.
+///
+/// # Implementation notes
+///
+/// On Linux, every chunk of the file is spliced into a pipe once and duplicated into a pipe per stream using `tee()`.
+/// Each stream is sent its copy as soon as it is writable,
+/// but the next chunk is only read once every stream has received the current one,
+/// so a slow stream delays all others.
+/// A stream which makes no progress for longer than its write timeout, as set using `set_write_timeout()`,
+/// fails with an error of kind `TimedOut` and is removed, while a stream without one is waited for indefinitely.
+/// The streams are put into non-blocking mode during the transfer.
+///
+/// On other platforms, or if `splice()` does not support the file,
+/// the file is sent to the streams one after another like using [`send_file()`].
+///
+/// # Example
+///
+/// ```
+/// use snedfile::broadcast;
+/// # use std::io;
+/// # use std::fs::File;
+/// # use std::net::TcpStream;
+///
+/// fn distribute(artifact: &File, peers: &[&TcpStream]) -> io::Result<()> {
+///     for (index, result) in broadcast(artifact, peers)?.into_iter().enumerate() {
+///         if let Err(e) = result {
+///             eprintln!("sending to peer {} failed: {}", index, e);
+///         };
+///     }
+///
+///     Ok(())
+/// }
+/// ```
+///
+/// [`send_file()`]: fn.send_file.html
+#[inline]
+pub fn broadcast(file: &File, streams: &[&TcpStream]) -> io::Result<Vec<io::Result<u64>>> {
+    broadcast::broadcast(file, streams)
+}
+
+/// Receives `length` bytes from a TCP stream into a file.
+///
+/// This is the counterpart of [`send_file()`].
+/// The data is written at the current file offset, which is advanced.
+/// Trivial errors are handled like in `send_file()`.
+///
+/// The amount of bytes received is returned,
+/// which is less than `length` only if the stream has reached the end before.
+/// See [`RecvOptions`] for preallocating the file and syncing it to the disk.
+///
+/// # Implementation notes
+///
+/// On Linux, the data is moved from the stream through a pipe into the file using `splice()`,
+/// without copying it into userspace.
+/// On other platforms, or if `splice()` does not support the file,
+/// it is copied using buffers from the global [`BufferPool`].
+///
+/// # Example
+///
+/// ```
+/// use snedfile::{recv_file, RecvOptions};
+/// # use std::io::{self, Error, ErrorKind};
+/// # use std::fs::File;
+/// # use std::net::TcpStream;
+///
+/// fn store_upload(stream: &mut TcpStream, file: &mut File, length: u64) -> io::Result<()> {
+///     if recv_file(stream, file, length, RecvOptions::default())? < length {
+///         return Err(Error::from(ErrorKind::UnexpectedEof));
+///     };
+///
+///     Ok(())
+/// }
+/// ```
+///
+/// [`send_file()`]: fn.send_file.html
+/// [`RecvOptions`]: struct.RecvOptions.html
+/// [`BufferPool`]: struct.BufferPool.html
+#[inline]
+pub fn recv_file(
+    stream: &mut TcpStream,
+    file: &mut File,
+    length: u64,
+    options: RecvOptions,
+) -> io::Result<u64> {
+    recv::recv(stream, file, Some(length), options)
+}
+
+/// Receives everything from a TCP stream into a file, until the stream reaches the end.
+///
+/// This behaves like [`recv_file()`] without a length, and returns the amount of bytes received.
+///
+/// [`recv_file()`]: fn.recv_file.html
+#[inline]
+pub fn recv_to_end(
+    stream: &mut TcpStream,
+    file: &mut File,
+    options: RecvOptions,
+) -> io::Result<u64> {
+    recv::recv(stream, file, None, options)
+}
+
+/// Copies a range of one file into another without moving the data through userspace, if possible.
+///
+/// The bytes of `src` within `range` are written at the current file offset of `dst`, which is advanced.
+/// The file offset of `src` is not changed.
+/// The range is limited to the length of `src`, so `..` copies the entire file.
+///
+/// The amount of bytes copied and the method which was used are returned.
+///
+/// # Implementation notes
+///
+/// On Linux, the methods are tried in this order, until one supports the files:
+///
+/// 1. Sharing the data using a reflink with `ioctl(FICLONE)` or `ioctl(FICLONERANGE)`,
+///    which requires a file system like Btrfs or XFS and ranges aligned to blocks.
+/// 2. `copy_file_range()`, which is unsupported across file systems before Linux 5.3.
+/// 3. `sendfile()`, which supports files as the destination since Linux 2.6.33.
+/// 4. A buffer from the global [`BufferPool`].
+///
+/// On other platforms, the data is always copied using the buffer.
+///
+/// # Example
+///
+/// ```
+/// use snedfile::{copy_file, CopyMethod};
+/// # use std::io;
+/// # use std::fs::File;
+///
+/// fn store_artifact(artifact: &File, cache: &File) -> io::Result<()> {
+///     let (_, method) = copy_file(artifact, cache, ..)?;
+///
+///     if method != CopyMethod::Reflink {
+///         eprintln!("the cache uses additional disk space");
+///     };
+///
+///     Ok(())
+/// }
+/// ```
+///
+/// [`BufferPool`]: struct.BufferPool.html
+#[inline]
+pub fn copy_file<R: RangeBounds<u64>>(
+    src: &File,
+    dst: &File,
+    range: R,
+) -> io::Result<(u64, CopyMethod)> {
+    copy::copy_file(src, dst, range)
+}
+
+/// Sends the entire contents of multiple files back-to-back to a TCP stream.
+///
+/// The files are sent in order, as if they were one file,
+/// and each of them is always sent starting at offset `0` without changing the file offset.
+/// The stream is corked for the entire transfer, so that no partially filled segments are sent between the files.
+/// Afterwards, it is restored to its previous state, so a stream which was already corked stays corked.
+/// If the stream is non-blocking, the transfer waits for it to become writable instead of spinning.
+///
+/// The amount of bytes sent of every file is returned.
+/// The transfer stops at the first error, which records the failed file and the offset within it.
+///
+/// # Implementation notes
+///
+/// On Linux and android `TCP_CORK` is used,
+/// and on MacOS, iOS, FreeBSD and DragonFlyBSD `TCP_NOPUSH`.
+/// On other platforms the stream is not corked.
+///
+/// # Example
+///
+/// ```
+/// use snedfile::send_files;
+/// # use std::io;
+/// # use std::fs::File;
+/// # use std::net::TcpStream;
+///
+/// fn serve_bundle(parts: &[&File], stream: &mut TcpStream) -> io::Result<u64> {
+///     let sent = send_files(parts, stream)?;
+///
+///     Ok(sent.iter().sum())
+/// }
+/// ```
+#[inline]
+pub fn send_files(files: &[&File], stream: &mut TcpStream) -> Result<Vec<u64>, SendFilesError> {
+    batch::send_files(files, stream)
+}
+
+/// Sends the contents of a file to a TCP stream, and keeps sending whatever is appended to it, like `tail -f`.
+///
+/// The existing contents are sent starting at offset `0`, after which the file is watched for growth.
+/// If the file is truncated, it is sent again from the start.
+/// The file offset is not changed.
+///
+/// This only returns once `cancel` is cancelled or an error occurs,
+/// for example because the peer has closed the connection.
+/// After a cancellation, the amount of bytes sent is returned.
+///
+/// # Implementation notes
+///
+/// On Linux, `inotify` is used to wake up as soon as the file is modified.
+/// On other platforms, or if `inotify` is unavailable, the length of the file is polled every quarter second.
+///
+/// # Example
+///
+/// ```
+/// use snedfile::{follow, CancelToken};
+/// # use std::io;
+/// # use std::fs::File;
+/// # use std::net::TcpStream;
+///
+/// fn stream_log(log: &File, stream: &mut TcpStream, shutdown: &CancelToken) -> io::Result<()> {
+///     let sent = follow(log, stream, shutdown)?;
+///     println!("{} bytes of log sent", sent);
+///
+///     Ok(())
+/// }
+/// ```
+#[inline]
+pub fn follow(file: &File, stream: &mut TcpStream, cancel: &CancelToken) -> io::Result<u64> {
+    follow::follow(file, stream, cancel)
+}
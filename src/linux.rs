@@ -26,7 +26,10 @@ mod sendfile {
 
 use sendfile::*;
 
+use crate::cache;
 use crate::fallback;
+use crate::unsupported;
+use crate::NoDiskIo;
 
 use libc::off_t;
 use std::fs::File;
@@ -36,6 +39,10 @@ use std::os::unix::io::AsRawFd;
 
 #[cfg(not(feature = "large-files"))]
 pub fn send_file(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
+    if unsupported::is_unsupported(file, stream) {
+        return fallback::send_file_from_start(file, stream);
+    };
+
     let length = file.metadata()?.len();
 
     if length == 0 {
@@ -55,6 +62,11 @@ pub fn send_file(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
         ) {
             Ok(sent) => sent,
             Err((ref e, ref sent)) if check_error(e.kind()) => *sent,
+            Err((ref e, 0)) if offset == 0 && unsupported::check(e) => {
+                unsupported::mark_unsupported(file, stream);
+
+                return fallback::send_file_from_start(file, stream);
+            }
             Err(e) => return Err(e.0),
         };
 
@@ -66,6 +78,10 @@ pub fn send_file(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
 
 #[cfg(feature = "large-files")]
 pub fn send_file(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
+    if unsupported::is_unsupported(file, stream) {
+        return fallback::send_file_from_start(file, stream);
+    };
+
     let length = file.metadata()?.len();
 
     if length == 0 {
@@ -92,6 +108,11 @@ pub fn send_file(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
         ) {
             Ok(sent) => sent,
             Err((ref e, sent)) if check_error(e.kind()) => sent,
+            Err((ref e, 0)) if offset == 0 && unsupported::check(e) => {
+                unsupported::mark_unsupported(file, stream);
+
+                return fallback::send_file_from_start(file, stream);
+            }
             Err(e) => return Err(e.0),
         };
 
@@ -105,6 +126,48 @@ pub fn send_file(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
     Ok(())
 }
 
+/// Sends at most `length` bytes at `offset` using a single system call.
+///
+/// The amount of bytes sent is returned, which is `0` at the end of the file.
+/// Errors like `WouldBlock` are only returned if nothing has been sent.
+pub fn send_chunk(file: &File, stream: &TcpStream, offset: u64, length: u64) -> io::Result<u64> {
+    match try_send_chunk(file, stream, offset, length)? {
+        Some(sent) => Ok(sent),
+        None => fallback::send_chunk(file, stream, offset, length),
+    }
+}
+
+/// Like [`send_chunk()`], but returns `None` instead of falling back to reading the file
+/// if the native `sendfile()` does not support the file, the stream or the offset.
+///
+/// [`send_chunk()`]: fn.send_chunk.html
+pub fn try_send_chunk(
+    file: &File,
+    stream: &TcpStream,
+    offset: u64,
+    length: u64,
+) -> io::Result<Option<u64>> {
+    if offset > off_t::MAX as u64 || unsupported::is_unsupported(file, stream) {
+        return Ok(None);
+    };
+
+    match try_sendfile(
+        file.as_raw_fd(),
+        stream.as_raw_fd(),
+        offset as off_t,
+        length.min(MAX_CHUNK) as usize,
+    ) {
+        Ok(sent) => Ok(Some(sent as u64)),
+        Err((_, sent)) if sent > 0 => Ok(Some(sent as u64)),
+        Err((ref e, _)) if unsupported::check(e) => {
+            unsupported::mark_unsupported(file, stream);
+
+            Ok(None)
+        }
+        Err((e, _)) => Err(e),
+    }
+}
+
 #[inline]
 fn check_error(e: ErrorKind) -> bool {
     e == ErrorKind::WouldBlock || e == ErrorKind::Interrupted
@@ -126,6 +189,10 @@ pub fn send_exact(
         };
     }
 
+    if unsupported::is_unsupported(file, stream) {
+        return fallback::send_exact(file, stream, length, offset);
+    };
+
     let length = if length > MAX_CHUNK {
         MAX_CHUNK
     } else {
@@ -139,6 +206,23 @@ pub fn send_exact(
         length as usize,
     ) {
         Ok(length) => Ok(length as u64),
+        Err((ref e, 0)) if unsupported::check(e) => {
+            unsupported::mark_unsupported(file, stream);
+
+            fallback::send_exact(file, stream, length, offset)
+        }
         Err(e) => Err(e.0),
     }
 }
+
+pub fn send_exact_nodiskio(
+    file: &mut File,
+    stream: &mut TcpStream,
+    length: u64,
+    offset: u64,
+) -> io::Result<NoDiskIo> {
+    match cache::resident(file, offset, length)? {
+        0 if length != 0 => Ok(NoDiskIo::WouldBlockOnDisk),
+        resident => send_exact(file, stream, resident, offset).map(NoDiskIo::Sent),
+    }
+}
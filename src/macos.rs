@@ -10,7 +10,7 @@ mod sendfile {
         stream: c_int,
         offset: off_t,
         mut length: off_t,
-    ) -> Result<(), (Error, off_t)> {
+    ) -> Result<off_t, (Error, off_t)> {
         if unsafe {
             libc::sendfile(
                 file,
@@ -24,14 +24,17 @@ mod sendfile {
         {
             Err((Error::last_os_error(), length))
         } else {
-            Ok(())
+            Ok(length)
         }
     }
 }
 
 use sendfile::*;
 
+use crate::cache;
 use crate::fallback;
+use crate::unsupported;
+use crate::NoDiskIo;
 
 use libc::off_t;
 use std::fs::File;
@@ -41,6 +44,10 @@ use std::os::unix::io::AsRawFd;
 
 #[cfg(not(feature = "large-files"))]
 pub fn send_file(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
+    if unsupported::is_unsupported(file, stream) {
+        return fallback::send_file_from_start(file, stream);
+    };
+
     let mut offset: off_t = 0;
 
     loop {
@@ -49,7 +56,7 @@ pub fn send_file(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
         match try_sendfile(file.as_raw_fd(), stream.as_raw_fd(), offset, 0) {
             // using match guards is not possible because we return at the special case below
             // and `Error` does not implement a way to convert `&Error` back to `Error`
-            Ok(()) => return Ok(()),
+            Ok(_) => return Ok(()),
             Err((e, sent)) => {
                 if check_error(e.kind()) {
                     if e.kind() == ErrorKind::Interrupted && sent == 0 {
@@ -58,6 +65,10 @@ pub fn send_file(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
                     }
 
                     offset += sent;
+                } else if offset == 0 && sent == 0 && unsupported::check(&e) {
+                    unsupported::mark_unsupported(file, stream);
+
+                    return fallback::send_file_from_start(file, stream);
                 } else {
                     return Err(e);
                 }
@@ -68,11 +79,15 @@ pub fn send_file(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
 
 #[cfg(feature = "large-files")]
 pub fn send_file(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
+    if unsupported::is_unsupported(file, stream) {
+        return fallback::send_file_from_start(file, stream);
+    };
+
     let mut offset: off_t = 0;
 
     loop {
         match try_sendfile(file.as_raw_fd(), stream.as_raw_fd(), offset, 0) {
-            Ok(()) => return Ok(()),
+            Ok(_) => return Ok(()),
             Err((e, sent)) => {
                 if check_error(e.kind()) {
                     if e.kind() == ErrorKind::Interrupted && sent == 0 {
@@ -89,6 +104,10 @@ pub fn send_file(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
                         // continue with the updated offset
                         offset = new_offset;
                     }
+                } else if offset == 0 && sent == 0 && unsupported::check(&e) {
+                    unsupported::mark_unsupported(file, stream);
+
+                    return fallback::send_file_from_start(file, stream);
                 } else {
                     return Err(e);
                 }
@@ -97,12 +116,64 @@ pub fn send_file(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
     }
 }
 
+/// Sends at most `length` bytes at `offset` using a single system call.
+///
+/// The amount of bytes sent is returned, which is `0` at the end of the file.
+/// Errors like `WouldBlock` are only returned if nothing has been sent.
+pub fn send_chunk(file: &File, stream: &TcpStream, offset: u64, length: u64) -> io::Result<u64> {
+    match try_send_chunk(file, stream, offset, length)? {
+        Some(sent) => Ok(sent),
+        None => fallback::send_chunk(file, stream, offset, length),
+    }
+}
+
+/// Like [`send_chunk()`], but returns `None` instead of falling back to reading the file
+/// if the native `sendfile()` does not support the file, the stream or the offset.
+///
+/// [`send_chunk()`]: fn.send_chunk.html
+pub fn try_send_chunk(
+    file: &File,
+    stream: &TcpStream,
+    offset: u64,
+    length: u64,
+) -> io::Result<Option<u64>> {
+    // a length of 0 would send the entire file
+    if length == 0 {
+        return Ok(Some(0));
+    };
+
+    if offset > off_t::MAX as u64 || unsupported::is_unsupported(file, stream) {
+        return Ok(None);
+    };
+
+    match try_sendfile(
+        file.as_raw_fd(),
+        stream.as_raw_fd(),
+        offset as off_t,
+        length.min(off_t::MAX as u64) as off_t,
+    ) {
+        Ok(sent) => Ok(Some(sent as u64)),
+        Err((_, sent)) if sent > 0 => Ok(Some(sent as u64)),
+        Err((ref e, _)) if unsupported::check(e) => {
+            unsupported::mark_unsupported(file, stream);
+
+            Ok(None)
+        }
+        Err((e, _)) => Err(e),
+    }
+}
+
 #[inline]
 fn check_error(e: ErrorKind) -> bool {
     e == ErrorKind::WouldBlock || e == ErrorKind::Interrupted
 }
 
-pub fn send_exact(file: &File, stream: &TcpStream, length: u64, offset: u64) -> io::Result<u64> {
+pub fn send_exact(
+    file: &mut File,
+    stream: &mut TcpStream,
+    length: u64,
+    offset: u64,
+) -> io::Result<u64> {
     #[cfg(feature = "large-files")]
     {
         if offset > off_t::max_value() as u64 {
@@ -113,6 +184,10 @@ pub fn send_exact(file: &File, stream: &TcpStream, length: u64, offset: u64) ->
         };
     }
 
+    if unsupported::is_unsupported(file, stream) {
+        return fallback::send_exact(file, stream, length, offset);
+    };
+
     let length = if length > off_t::max_value() as u64 {
         off_t::max_value()
     } else {
@@ -125,7 +200,24 @@ pub fn send_exact(file: &File, stream: &TcpStream, length: u64, offset: u64) ->
         offset as off_t,
         length,
     ) {
-        Ok(()) => Ok(length as u64),
+        Ok(sent) => Ok(sent as u64),
+        Err((ref e, 0)) if unsupported::check(e) => {
+            unsupported::mark_unsupported(file, stream);
+
+            fallback::send_exact(file, stream, length as u64, offset)
+        }
         Err((e, _)) => Err(e),
     }
 }
+
+pub fn send_exact_nodiskio(
+    file: &mut File,
+    stream: &mut TcpStream,
+    length: u64,
+    offset: u64,
+) -> io::Result<NoDiskIo> {
+    match cache::resident(file, offset, length)? {
+        0 if length != 0 => Ok(NoDiskIo::WouldBlockOnDisk),
+        resident => send_exact(file, stream, resident, offset).map(NoDiskIo::Sent),
+    }
+}
@@ -0,0 +1,162 @@
+/*!
+Driving file transfers from a [`mio`] event loop.
+
+This module is only available with the `mio` feature.
+
+[`mio`]: https://docs.rs/mio
+*/
+
+use ::mio::net::TcpStream;
+use ::mio::{Interest, Registry, Token};
+
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::mem::ManuallyDrop;
+
+/// The state of a [`Transfer`] after advancing it.
+///
+/// [`Transfer`]: struct.Transfer.html
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Progress {
+    /// The entire file has been sent, which were this many bytes.
+    Done(u64),
+    /// The stream is not writable anymore, and this many bytes have been sent so far.
+    ///
+    /// The transfer should be advanced again on the next writable event.
+    Pending(u64),
+}
+
+/// A resumable transfer of a file to a non-blocking `mio` TCP stream.
+///
+/// # Example
+///
+/// ```no_run
+/// use mio::net::TcpStream;
+/// use mio::{Events, Poll, Token};
+/// use snedfile::mio::{Progress, Transfer};
+/// # use std::io;
+/// # use std::fs::File;
+///
+/// fn serve_static(file: File, mut stream: TcpStream) -> io::Result<u64> {
+///     let mut poll = Poll::new()?;
+///     let mut events = Events::with_capacity(16);
+///     let mut transfer = Transfer::new(file)?;
+///
+///     transfer.register(poll.registry(), &mut stream, Token(0))?;
+///
+///     loop {
+///         poll.poll(&mut events, None)?;
+///
+///         for event in events.iter() {
+///             if event.is_writable() {
+///                 if let Progress::Done(sent) = transfer.advance(&stream)? {
+///                     return Ok(sent);
+///                 };
+///             };
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Transfer {
+    file: File,
+    offset: u64,
+    length: u64,
+}
+
+impl Transfer {
+    /// Creates a transfer of the entire file.
+    ///
+    /// The file is always sent starting at offset `0`, and the file offset is not changed.
+    pub fn new(file: File) -> io::Result<Transfer> {
+        let length = file.metadata()?.len();
+
+        Ok(Transfer {
+            file,
+            offset: 0,
+            length,
+        })
+    }
+
+    /// Registers the stream for writable events.
+    #[inline]
+    pub fn register(
+        &self,
+        registry: &Registry,
+        stream: &mut TcpStream,
+        token: Token,
+    ) -> io::Result<()> {
+        registry.register(stream, token, Interest::WRITABLE)
+    }
+
+    /// Sends as much of the file as possible without blocking.
+    ///
+    /// This keeps sending until the stream returns `WouldBlock` or the file has been sent entirely,
+    /// as required for edge-triggered readiness events,
+    /// so the transfer only needs to be advanced once per writable event.
+    /// `Interrupted` errors are handled.
+    pub fn advance(&mut self, stream: &TcpStream) -> io::Result<Progress> {
+        let stream = borrow_stream(stream);
+
+        while self.offset < self.length {
+            match crate::imp::send_chunk(
+                &self.file,
+                &stream,
+                self.offset,
+                self.length - self.offset,
+            ) {
+                Ok(0) => break, // the file has been truncated
+                Ok(sent) => self.offset += sent,
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                    return Ok(Progress::Pending(self.offset));
+                }
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+        }
+
+        Ok(Progress::Done(self.offset))
+    }
+
+    /// Returns the amount of bytes sent so far.
+    #[inline]
+    pub fn sent(&self) -> u64 {
+        self.offset
+    }
+
+    /// Returns the total amount of bytes to send.
+    #[inline]
+    pub fn len(&self) -> u64 {
+        self.length
+    }
+
+    /// Returns `true` if the file is empty.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.length == 0
+    }
+
+    /// Returns the file.
+    #[inline]
+    pub fn into_inner(self) -> File {
+        self.file
+    }
+}
+
+/// Borrows the descriptor of the stream as a standard library `TcpStream`,
+/// which the native implementations use.
+#[cfg(unix)]
+fn borrow_stream(stream: &TcpStream) -> ManuallyDrop<std::net::TcpStream> {
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    ManuallyDrop::new(unsafe { std::net::TcpStream::from_raw_fd(stream.as_raw_fd()) })
+}
+
+/// Borrows the socket of the stream as a standard library `TcpStream`,
+/// which the fallback uses.
+#[cfg(windows)]
+fn borrow_stream(stream: &TcpStream) -> ManuallyDrop<std::net::TcpStream> {
+    use std::os::windows::io::{AsRawSocket, FromRawSocket};
+
+    ManuallyDrop::new(unsafe { std::net::TcpStream::from_raw_socket(stream.as_raw_socket()) })
+}
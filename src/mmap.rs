@@ -0,0 +1,205 @@
+use crate::cache;
+use crate::fallback;
+use crate::wait;
+
+use libc::{c_void, off_t};
+use std::fs::File;
+use std::io::{self, Error, ErrorKind};
+use std::net::TcpStream;
+use std::os::unix::io::AsRawFd;
+use std::ptr;
+
+/// The window size used by the `fallback-mmap` feature.
+#[cfg(feature = "fallback-mmap")]
+pub const DEFAULT_WINDOW: usize = 64 * 1024 * 1024;
+
+/// A read-only shared mapping of a part of a file, which is unmapped on drop.
+struct Mapping {
+    ptr: *mut c_void,
+    length: usize,
+}
+
+impl Mapping {
+    fn new(file: &File, offset: off_t, length: usize) -> io::Result<Mapping> {
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                length,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                offset,
+            )
+        };
+
+        if ptr == libc::MAP_FAILED {
+            Err(Error::last_os_error())
+        } else {
+            Ok(Mapping { ptr, length })
+        }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr, self.length);
+        }
+    }
+}
+
+/// Sends the entire file by mapping windows of it into memory and writing them to the stream.
+///
+/// The mapped memory is never touched in userspace, only by the kernel inside `write()`.
+/// If the file is truncated during the transfer, accessing the vanished pages would raise `SIGBUS` in userspace,
+/// but inside the system call it results in a short write followed by `EFAULT`.
+/// In that case, the rest of the file is transmitted using `read()` instead,
+/// as is a file which cannot be mapped at all.
+pub fn send_file(file: &mut File, stream: &mut TcpStream, window: usize) -> io::Result<()> {
+    let window = page_align(window) as u64;
+
+    let mut offset: u64 = 0;
+
+    loop {
+        // the length is queried for every window, so that truncation is noticed before mapping
+        let length = file.metadata()?.len();
+
+        if offset >= length {
+            return Ok(());
+        };
+
+        if offset > off_t::MAX as u64 {
+            return fallback::copy_to_end(file, stream, offset);
+        };
+
+        let size = window.min(length - offset) as usize;
+        let mapping = match Mapping::new(file, offset as off_t, size) {
+            Ok(mapping) => mapping,
+            // some files, like the attributes in `/sys`, cannot be mapped but can still be read
+            Err(_) => return fallback::copy_to_end(file, stream, offset),
+        };
+
+        match write_mapping(stream, &mapping) {
+            Ok(()) => offset += size as u64,
+            Err((ref e, sent)) if e.raw_os_error() == Some(libc::EFAULT) => {
+                drop(mapping);
+
+                return fallback::copy_to_end(file, stream, offset + sent as u64);
+            }
+            Err((e, _)) => return Err(e),
+        };
+    }
+}
+
+fn write_mapping(stream: &TcpStream, mapping: &Mapping) -> Result<(), (Error, usize)> {
+    let mut sent = 0;
+
+    while sent < mapping.length {
+        match unsafe {
+            libc::write(
+                stream.as_raw_fd(),
+                (mapping.ptr as *const u8).add(sent) as *const c_void,
+                mapping.length - sent,
+            )
+        } {
+            -1 => {
+                let e = Error::last_os_error();
+
+                match e.kind() {
+                    ErrorKind::WouldBlock => wait::writable(stream).map_err(|e| (e, sent))?,
+                    ErrorKind::Interrupted => (),
+                    _ => return Err((e, sent)),
+                };
+            }
+            0 => return Err((Error::from(ErrorKind::WriteZero), sent)),
+            written => sent += written as usize,
+        };
+    }
+
+    Ok(())
+}
+
+/// Rounds the window up to a multiple of the page size, so that every mapping offset is aligned.
+///
+/// Windows too large to be rounded up are rounded down instead.
+fn page_align(window: usize) -> usize {
+    let page = cache::page_size() as usize;
+
+    window
+        .max(1)
+        .div_ceil(page)
+        .checked_mul(page)
+        .unwrap_or(usize::MAX / page * page)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+
+    #[test]
+    fn page_align() {
+        let page = crate::cache::page_size() as usize;
+
+        assert_eq!(super::page_align(1), page);
+        assert_eq!(super::page_align(page + 1), 2 * page);
+        assert_eq!(super::page_align(usize::MAX) % page, 0);
+    }
+
+    #[test]
+    fn multiple_windows() {
+        let mut file = tempfile::tempfile().unwrap();
+        let (mut a, mut b) = tcp_test::channel();
+        let data: Vec<u8> = (0..20_000u32).map(|i| (i % 251) as u8).collect();
+
+        file.write_all(&data).unwrap();
+
+        super::send_file(&mut file, &mut a, 1).unwrap();
+
+        let mut buf = vec![0; data.len()];
+        b.read_exact(&mut buf).unwrap();
+        assert_eq!(data, buf);
+    }
+
+    #[test]
+    fn truncated() {
+        let mut file = tempfile::tempfile().unwrap();
+        let (mut a, mut b) = tcp_test::channel();
+        let data: Vec<u8> = (0..64 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+
+        file.write_all(&data).unwrap();
+
+        let shrink = file.try_clone().unwrap();
+
+        let receiver = std::thread::spawn(move || {
+            let mut buf = vec![0; 1024 * 1024];
+            b.read_exact(&mut buf).unwrap();
+
+            // the sender is still blocked on the first window, whose pages vanish now
+            shrink.set_len(4 * 1024 * 1024).unwrap();
+
+            b.read_to_end(&mut buf).unwrap();
+            buf
+        });
+
+        super::send_file(&mut file, &mut a, 64 * 1024 * 1024).unwrap();
+        drop(a);
+
+        let buf = receiver.join().unwrap();
+        assert!(buf.len() >= 4 * 1024 * 1024 && buf.len() < data.len());
+        assert_eq!(data[..buf.len()], buf[..]);
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn unmappable() {
+        let mut file = std::fs::File::open("/sys/devices/system/cpu/online").unwrap();
+        let (mut a, mut b) = tcp_test::channel();
+
+        super::send_file(&mut file, &mut a, 4096).unwrap();
+        drop(a);
+
+        let mut buf = Vec::new();
+        b.read_to_end(&mut buf).unwrap();
+        assert!(!buf.is_empty());
+    }
+}
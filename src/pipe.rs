@@ -0,0 +1,122 @@
+use libc::c_int;
+use std::cell::RefCell;
+use std::io::{self, Error};
+use std::os::unix::io::RawFd;
+
+/// The capacity requested for pooled pipes using `F_SETPIPE_SZ`.
+///
+/// If it exceeds `/proc/sys/fs/pipe-max-size`, the default capacity is used instead.
+const PIPE_SIZE: c_int = 1024 * 1024;
+
+/// The maximum amount of idle pipes kept per thread.
+const MAX_IDLE: usize = 4;
+
+thread_local! {
+    static POOL: RefCell<Vec<Pipe>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Both ends of a pipe, which are closed on drop.
+pub struct Pipe {
+    pub read: RawFd,
+    pub write: RawFd,
+}
+
+impl Pipe {
+    pub fn new() -> io::Result<Pipe> {
+        let mut fds = [0; 2];
+
+        if unsafe { libc::pipe2(fds.as_mut_ptr(), libc::O_CLOEXEC) } == -1 {
+            Err(Error::last_os_error())
+        } else {
+            Ok(Pipe {
+                read: fds[0],
+                write: fds[1],
+            })
+        }
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.read);
+            libc::close(self.write);
+        }
+    }
+}
+
+/// A pipe taken from the pool of the current thread.
+///
+/// On drop, it is returned to the pool if it is empty, and closed otherwise.
+pub struct PooledPipe {
+    pipe: Option<Pipe>,
+    capacity: usize,
+    /// The amount of bytes which have been written into the pipe, but not read from it yet.
+    pub buffered: usize,
+}
+
+impl PooledPipe {
+    /// Takes an idle pipe from the pool, or creates a new one.
+    pub fn take() -> io::Result<PooledPipe> {
+        let pipe = match POOL.with(|pool| pool.borrow_mut().pop()) {
+            Some(pipe) => pipe,
+            None => {
+                let pipe = Pipe::new()?;
+
+                // a larger pipe needs fewer `splice()` calls, but is not required
+                unsafe { libc::fcntl(pipe.write, libc::F_SETPIPE_SZ, PIPE_SIZE) };
+
+                pipe
+            }
+        };
+
+        let capacity = match unsafe { libc::fcntl(pipe.write, libc::F_GETPIPE_SZ) } {
+            -1 => 64 * 1024,
+            capacity => capacity as usize,
+        };
+
+        Ok(PooledPipe {
+            pipe: Some(pipe),
+            capacity,
+            buffered: 0,
+        })
+    }
+
+    /// Returns the end which is read from.
+    #[inline]
+    pub fn read(&self) -> RawFd {
+        self.pipe.as_ref().expect("pipe").read
+    }
+
+    /// Returns the end which is written to.
+    #[inline]
+    pub fn write(&self) -> RawFd {
+        self.pipe.as_ref().expect("pipe").write
+    }
+
+    /// Returns the amount of bytes the pipe can hold.
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl Drop for PooledPipe {
+    fn drop(&mut self) {
+        // a pipe which still contains data would leak it into the next transfer
+        if self.buffered != 0 {
+            return;
+        };
+
+        if let Some(pipe) = self.pipe.take() {
+            // the pool may already be destroyed if this runs during thread exit
+            let _ = POOL.try_with(|pool| {
+                let mut pool = pool.borrow_mut();
+
+                if pool.len() < MAX_IDLE {
+                    pool.push(pipe);
+                };
+            });
+        };
+    }
+}
@@ -0,0 +1,315 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Mutex;
+use std::thread;
+
+/// The maximum amount of idle buffers kept by a pool.
+const MAX_IDLE: usize = 16;
+
+static GLOBAL: BufferPool = BufferPool::new(BufferPool::DEFAULT_BUFFER_SIZE);
+
+/// A pool of fixed-size buffers which are reused across transfers.
+///
+/// Copying through a pool holds one buffer per transfer, or two for [`copy_read_ahead()`],
+/// so memory usage does not depend on the size of the transmitted files.
+/// At most 16 idle buffers are kept for later transfers.
+///
+/// # Example
+///
+/// ```
+/// use snedfile::{send_file_with_pool, BufferPool};
+/// # use std::io;
+/// # use std::fs::File;
+/// # use std::net::TcpStream;
+///
+/// fn serve_static(file: &mut File, stream: &mut TcpStream, pool: &BufferPool) -> io::Result<()> {
+///     send_file_with_pool(file, stream, pool)
+/// }
+/// ```
+///
+/// [`copy_read_ahead()`]: #method.copy_read_ahead
+#[derive(Debug)]
+pub struct BufferPool {
+    buffer_size: AtomicUsize,
+    buffers: Mutex<Vec<Vec<u8>>>,
+}
+
+impl BufferPool {
+    /// The buffer size of the global pool, unless changed using `set_buffer_size()`.
+    pub const DEFAULT_BUFFER_SIZE: usize = 64 * 1024;
+
+    /// Creates an empty pool handing out buffers of `buffer_size` bytes.
+    ///
+    /// A `buffer_size` of `0` is treated as `1`.
+    pub const fn new(buffer_size: usize) -> BufferPool {
+        BufferPool {
+            buffer_size: AtomicUsize::new(if buffer_size == 0 { 1 } else { buffer_size }),
+            buffers: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the pool used by the `fallback-buf` feature.
+    #[inline]
+    pub fn global() -> &'static BufferPool {
+        &GLOBAL
+    }
+
+    /// Returns the size of the buffers handed out by this pool.
+    #[inline]
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size.load(Ordering::Relaxed)
+    }
+
+    /// Changes the size of the buffers handed out by this pool.
+    ///
+    /// Idle buffers of the previous size are discarded.
+    /// A `buffer_size` of `0` is treated as `1`.
+    pub fn set_buffer_size(&self, buffer_size: usize) {
+        self.buffer_size
+            .store(buffer_size.max(1), Ordering::Relaxed);
+
+        if let Ok(mut buffers) = self.buffers.lock() {
+            buffers.clear();
+        };
+    }
+
+    /// Copies the entire contents of `reader` into `writer` using one buffer of this pool.
+    ///
+    /// The amount of bytes copied is returned.
+    /// `Interrupted` errors are handled.
+    pub fn copy<R: Read + ?Sized, W: Write + ?Sized>(
+        &self,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> io::Result<u64> {
+        let mut buf = self.take();
+        let result = copy_with(&mut buf, reader, writer);
+
+        self.give(buf);
+
+        result
+    }
+
+    /// Copies the entire contents of `reader` into `writer` using two buffers of this pool,
+    /// one of which is filled by a helper thread while the other is written.
+    ///
+    /// This overlaps blocking reads and blocking writes,
+    /// which helps if both the reader and the writer are slow, like a disk and a network connection.
+    ///
+    /// The amount of bytes copied is returned.
+    /// `Interrupted` errors are handled.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use snedfile::BufferPool;
+    /// # use std::io;
+    /// # use std::fs::File;
+    /// # use std::net::TcpStream;
+    ///
+    /// fn serve_static(file: &mut File, stream: &mut TcpStream) -> io::Result<u64> {
+    ///     BufferPool::global().copy_read_ahead(file, stream)
+    /// }
+    /// ```
+    pub fn copy_read_ahead<R: Read + Send + ?Sized, W: Write + ?Sized>(
+        &self,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> io::Result<u64> {
+        // full buffers travel from the helper thread to the writer, and empty ones back
+        let (full_sender, full_receiver) = mpsc::sync_channel(1);
+        let (empty_sender, empty_receiver) = mpsc::sync_channel(2);
+
+        for _ in 0..2 {
+            let _ = empty_sender.send(self.take());
+        }
+
+        let (result, buffers) = thread::scope(|scope| {
+            let helper = scope.spawn(move || read_ahead(reader, empty_receiver, full_sender));
+
+            // dropping the channels on errors makes the helper thread stop
+            let result = write_behind(writer, full_receiver, empty_sender);
+            let buffers = helper.join().unwrap_or_default();
+
+            (result, buffers)
+        });
+
+        for buf in buffers {
+            self.give(buf);
+        }
+
+        result
+    }
+
+    pub(crate) fn take(&self) -> Vec<u8> {
+        let size = self.buffer_size();
+        let idle = self
+            .buffers
+            .lock()
+            .ok()
+            .and_then(|mut buffers| buffers.pop());
+
+        match idle {
+            Some(buf) if buf.len() == size => buf,
+            _ => vec![0; size],
+        }
+    }
+
+    pub(crate) fn give(&self, buf: Vec<u8>) {
+        if buf.len() != self.buffer_size() {
+            return;
+        };
+
+        if let Ok(mut buffers) = self.buffers.lock() {
+            if buffers.len() < MAX_IDLE {
+                buffers.push(buf);
+            };
+        };
+    }
+}
+
+impl Default for BufferPool {
+    fn default() -> BufferPool {
+        BufferPool::new(BufferPool::DEFAULT_BUFFER_SIZE)
+    }
+}
+
+fn copy_with<R: Read + ?Sized, W: Write + ?Sized>(
+    buf: &mut [u8],
+    reader: &mut R,
+    writer: &mut W,
+) -> io::Result<u64> {
+    let mut copied = 0;
+
+    loop {
+        let read = match reader.read(buf) {
+            Ok(0) => return Ok(copied),
+            Ok(read) => read,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        writer.write_all(&buf[..read])?;
+        copied += read as u64;
+    }
+}
+
+/// The body of the helper thread of `copy_read_ahead()`, which returns the buffers it still owns.
+fn read_ahead<R: Read + ?Sized>(
+    reader: &mut R,
+    empty: Receiver<Vec<u8>>,
+    full: SyncSender<io::Result<(Vec<u8>, usize)>>,
+) -> Vec<Vec<u8>> {
+    let mut buffers = Vec::new();
+
+    while let Ok(mut buf) = empty.recv() {
+        let read = loop {
+            match reader.read(&mut buf) {
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                other => break other,
+            }
+        };
+
+        match read {
+            Ok(0) => {
+                buffers.push(buf);
+                break;
+            }
+            Ok(read) => {
+                if let Err(mpsc::SendError(Ok((buf, _)))) = full.send(Ok((buf, read))) {
+                    buffers.push(buf);
+                    break;
+                };
+            }
+            Err(e) => {
+                buffers.push(buf);
+                let _ = full.send(Err(e));
+                break;
+            }
+        };
+    }
+
+    // dropping `full` tells the writer that the end has been reached
+    drop(full);
+    buffers.extend(empty.iter());
+
+    buffers
+}
+
+fn write_behind<W: Write + ?Sized>(
+    writer: &mut W,
+    full: Receiver<io::Result<(Vec<u8>, usize)>>,
+    empty: SyncSender<Vec<u8>>,
+) -> io::Result<u64> {
+    let mut copied = 0;
+
+    for next in full.iter() {
+        let (buf, read) = next?;
+
+        writer.write_all(&buf[..read])?;
+        copied += read as u64;
+
+        // the helper thread may already have finished, in which case the buffer is dropped
+        let _ = empty.send(buf);
+    }
+
+    Ok(copied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BufferPool;
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    #[test]
+    fn copy() {
+        let pool = BufferPool::new(3);
+        let mut file = tempfile::tempfile().unwrap();
+        let (mut a, mut b) = tcp_test::channel();
+        let data = b"p0$Lx=Qe";
+
+        file.write_all(data).unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+
+        assert_eq!(pool.copy(&mut file, &mut a).unwrap(), 8);
+
+        let mut buf = [0; 8];
+        b.read_exact(&mut buf).unwrap();
+        assert_eq!(data, &buf);
+    }
+
+    #[test]
+    fn copy_read_ahead() {
+        let pool = BufferPool::new(5);
+        let mut file = tempfile::tempfile().unwrap();
+        let (mut a, mut b) = tcp_test::channel();
+        let data: Vec<u8> = (0..1000u32).map(|i| (i % 253) as u8).collect();
+
+        file.write_all(&data).unwrap();
+        file.seek(SeekFrom::Start(0)).unwrap();
+
+        assert_eq!(pool.copy_read_ahead(&mut file, &mut a).unwrap(), 1000);
+
+        let mut buf = vec![0; 1000];
+        b.read_exact(&mut buf).unwrap();
+        assert_eq!(data, buf);
+        assert_eq!(pool.buffers.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn reuse() {
+        let pool = BufferPool::new(4);
+
+        let buf = pool.take();
+        let ptr = buf.as_ptr();
+        pool.give(buf);
+
+        let buf = pool.take();
+        assert_eq!(buf.as_ptr(), ptr);
+
+        pool.set_buffer_size(8);
+        pool.give(vec![0; 4]);
+        assert_eq!(pool.take().len(), 8);
+    }
+}
@@ -0,0 +1,50 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// How often the progress callback of [`send_file_with_progress()`] is invoked.
+///
+/// [`send_file_with_progress()`]: fn.send_file_with_progress.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Granularity {
+    /// After every system call which sent data.
+    Chunk,
+    /// Whenever at least this many bytes have been sent since the last invocation.
+    Bytes(u64),
+}
+
+/// Returned by a progress callback to continue or abort the transfer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Control {
+    /// Continue the transfer.
+    Continue,
+    /// Stop the transfer, which then fails with an [`Aborted`] error.
+    ///
+    /// [`Aborted`]: struct.Aborted.html
+    Abort,
+}
+
+/// The error of a transfer aborted by its progress callback.
+///
+/// It is returned wrapped in an `io::Error` of kind `Other`,
+/// and can be recovered using `get_ref()` and `downcast_ref()`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Aborted {
+    /// The amount of bytes sent before the transfer was aborted.
+    pub sent: u64,
+}
+
+impl fmt::Display for Aborted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "transfer aborted after {} bytes", self.sent)
+    }
+}
+
+impl Error for Aborted {}
+
+impl From<Aborted> for io::Error {
+    #[inline]
+    fn from(e: Aborted) -> io::Error {
+        io::Error::other(e)
+    }
+}
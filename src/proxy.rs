@@ -0,0 +1,73 @@
+use std::io::{self, ErrorKind};
+use std::net::{Shutdown, TcpStream};
+use std::thread;
+
+#[cfg(target_os = "linux")]
+use crate::pipe::PooledPipe as Buffer;
+
+pub fn proxy(a: &TcpStream, b: &TcpStream) -> io::Result<(u64, u64)> {
+    // both buffers are taken from the pool of this thread, so that they are returned to it
+    // instead of being destroyed together with the other thread
+    let mut a_buffer = Buffer::take()?;
+    let mut b_buffer = Buffer::take()?;
+
+    thread::scope(|scope| {
+        let b_to_a = scope.spawn(|| pump(b, a, &mut b_buffer));
+        let a_to_b = pump(a, b, &mut a_buffer);
+        let b_to_a = b_to_a.join().expect("proxy thread panicked");
+
+        Ok((a_to_b?, b_to_a?))
+    })
+}
+
+/// Moves all data from `source` to `sink`, and closes the writing half of `sink` once `source` reaches the end.
+fn pump(source: &TcpStream, sink: &TcpStream, buffer: &mut Buffer) -> io::Result<u64> {
+    match copy(source, sink, buffer) {
+        Ok(moved) => {
+            shutdown(sink, Shutdown::Write)?;
+
+            Ok(moved)
+        }
+        Err(e) => {
+            // the other direction would block forever otherwise
+            let _ = source.shutdown(Shutdown::Both);
+            let _ = sink.shutdown(Shutdown::Both);
+
+            Err(e)
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[inline]
+fn copy(source: &TcpStream, sink: &TcpStream, pipe: &mut Buffer) -> io::Result<u64> {
+    use std::os::unix::io::AsRawFd;
+
+    crate::splice::copy_through(pipe, source.as_raw_fd(), sink.as_raw_fd())
+}
+
+/// The buffers are taken from the global pool by `copy()` itself.
+#[cfg(not(target_os = "linux"))]
+struct Buffer;
+
+#[cfg(not(target_os = "linux"))]
+impl Buffer {
+    #[inline]
+    fn take() -> io::Result<Buffer> {
+        Ok(Buffer)
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+#[inline]
+fn copy(source: &TcpStream, sink: &TcpStream, _buffer: &mut Buffer) -> io::Result<u64> {
+    crate::BufferPool::global().copy(&mut &*source, &mut &*sink)
+}
+
+/// Shuts the stream down, ignoring that the peer may already have closed the connection.
+fn shutdown(stream: &TcpStream, how: Shutdown) -> io::Result<()> {
+    match stream.shutdown(how) {
+        Err(ref e) if e.kind() == ErrorKind::NotConnected => Ok(()),
+        other => other,
+    }
+}
@@ -0,0 +1,185 @@
+use crate::wait;
+
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+/// Options for receiving data into a file using [`recv_file()`] and [`recv_to_end()`].
+///
+/// The default does neither preallocate nor sync.
+///
+/// # Example
+///
+/// ```
+/// use snedfile::{recv_file, RecvOptions};
+/// # use std::io;
+/// # use std::fs::File;
+/// # use std::net::TcpStream;
+///
+/// fn store_upload(stream: &mut TcpStream, file: &mut File, length: u64) -> io::Result<u64> {
+///     let options = RecvOptions {
+///         preallocate: true,
+///         sync: true,
+///     };
+///
+///     recv_file(stream, file, length, options)
+/// }
+/// ```
+///
+/// [`recv_file()`]: fn.recv_file.html
+/// [`recv_to_end()`]: fn.recv_to_end.html
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct RecvOptions {
+    /// Reserve the disk space for the expected length before receiving, without changing the file size.
+    ///
+    /// This reduces fragmentation and makes a full disk fail early.
+    /// It is only supported on Linux and android using `fallocate()`, and ignored elsewhere
+    /// or if the file system does not support it.
+    /// [`recv_to_end()`] does not know the length in advance and ignores it.
+    ///
+    /// [`recv_to_end()`]: fn.recv_to_end.html
+    pub preallocate: bool,
+    /// Flush the data and metadata of the file to the disk using `fsync()` after receiving.
+    pub sync: bool,
+}
+
+pub fn recv(
+    stream: &mut TcpStream,
+    file: &mut File,
+    length: Option<u64>,
+    options: RecvOptions,
+) -> io::Result<u64> {
+    if let Some(length) = length {
+        if options.preallocate {
+            preallocate(file, length)?;
+        };
+    };
+
+    let received = recv_imp(stream, file, length.unwrap_or(u64::MAX))?;
+
+    if options.sync {
+        file.sync_all()?;
+    };
+
+    Ok(received)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn preallocate(file: &File, length: u64) -> io::Result<()> {
+    use std::io::{Error, Seek};
+    use std::os::unix::io::AsRawFd;
+
+    if length == 0 || length > libc::off_t::MAX as u64 {
+        return Ok(());
+    };
+
+    // the data is written at the current file offset
+    let offset = (&*file).stream_position()?;
+
+    if unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            libc::FALLOC_FL_KEEP_SIZE,
+            offset as libc::off_t,
+            length as libc::off_t,
+        )
+    } == -1
+    {
+        let e = Error::last_os_error();
+
+        // preallocation is only an optimization
+        if e.raw_os_error() != Some(libc::EOPNOTSUPP) && e.raw_os_error() != Some(libc::ENOSYS) {
+            return Err(e);
+        };
+    };
+
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+#[inline]
+fn preallocate(_file: &File, _length: u64) -> io::Result<()> {
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn recv_imp(stream: &mut TcpStream, file: &mut File, length: u64) -> io::Result<u64> {
+    use crate::pipe::PooledPipe;
+    use crate::splice;
+    use crate::unsupported;
+
+    use std::os::unix::io::AsRawFd;
+
+    // `splice()` does not support files opened for appending
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETFL) } & libc::O_APPEND != 0 {
+        return copy(stream, file, length);
+    };
+
+    let mut pipe = PooledPipe::take()?;
+    let mut received = 0;
+
+    while received < length {
+        let chunk = (length - received).min(pipe.capacity() as u64) as usize;
+
+        match splice::fill(&mut pipe, stream.as_raw_fd(), None, chunk) {
+            Ok(0) => break, // the stream has reached the end
+            Ok(filled) => {
+                splice::drain(&mut pipe, file.as_raw_fd())?;
+                received += filled as u64;
+            }
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => wait::readable(stream)?,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(ref e) if received == 0 && unsupported::check(e) => {
+                return copy(stream, file, length);
+            }
+            Err(e) => return Err(e),
+        };
+    }
+
+    Ok(received)
+}
+
+#[cfg(not(target_os = "linux"))]
+#[inline]
+fn recv_imp(stream: &mut TcpStream, file: &mut File, length: u64) -> io::Result<u64> {
+    copy(stream, file, length)
+}
+
+/// Receives at most `length` bytes using a buffer from the global pool.
+fn copy(stream: &mut TcpStream, file: &mut File, length: u64) -> io::Result<u64> {
+    let pool = crate::BufferPool::global();
+    let mut buf = pool.take();
+
+    // the buffer is returned to the pool even if the transfer fails
+    let result = copy_through(stream, file, length, &mut buf);
+    pool.give(buf);
+
+    result
+}
+
+/// Receives at most `length` bytes through `buf`.
+fn copy_through(
+    stream: &mut TcpStream,
+    file: &mut File,
+    length: u64,
+    buf: &mut [u8],
+) -> io::Result<u64> {
+    let mut received = 0;
+
+    while received < length {
+        let chunk = (length - received).min(buf.len() as u64) as usize;
+
+        match stream.read(&mut buf[..chunk]) {
+            Ok(0) => break, // the stream has reached the end
+            Ok(read) => {
+                file.write_all(&buf[..read])?;
+                received += read as u64;
+            }
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => wait::readable(stream)?,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+    }
+
+    Ok(received)
+}
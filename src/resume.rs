@@ -0,0 +1,292 @@
+/*!
+Transfers which can be resumed after they have been interrupted.
+
+[`send_resumable()`] reports its progress as a [`Checkpoint`] every few bytes.
+If the transfer fails, or the process dies, it can be resumed from the last checkpoint,
+as long as the receiving side kept what it has received so far.
+
+The offset of a checkpoint counts the bytes handed to the operating system,
+some of which may not have reached the receiver when the connection fails.
+It is therefore only an upper bound, and a transfer should be resumed at the length the receiver actually has,
+which [`Checkpoint::acknowledged()`] applies.
+
+Every checkpoint contains a [`Fingerprint`] of the file,
+so that a changed file is not resumed, which would corrupt the transfer.
+
+[`send_resumable()`]: fn.send_resumable.html
+[`Checkpoint`]: struct.Checkpoint.html
+[`Fingerprint`]: struct.Fingerprint.html
+[`Checkpoint::acknowledged()`]: struct.Checkpoint.html#method.acknowledged
+*/
+
+use crate::checkpoint;
+use crate::wait;
+
+use std::fs::File;
+use std::io::{self, Error, ErrorKind};
+use std::net::TcpStream;
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Identifies the contents of a file by its length, modification time, device and inode.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Fingerprint {
+    /// The length of the file.
+    pub length: u64,
+    /// The modification time since the Unix epoch, if the platform supports it.
+    pub modified: Option<Duration>,
+    /// The device containing the file, or `0` on platforms without inodes.
+    pub device: u64,
+    /// The inode of the file, or `0` on platforms without inodes.
+    pub inode: u64,
+}
+
+impl Fingerprint {
+    /// Takes the fingerprint of a file.
+    pub fn of(file: &File) -> io::Result<Fingerprint> {
+        let metadata = file.metadata()?;
+        let (device, inode) = checkpoint::file_id(&metadata);
+
+        Ok(Fingerprint {
+            length: metadata.len(),
+            modified: metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok()),
+            device,
+            inode,
+        })
+    }
+}
+
+/// The progress of a resumable transfer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Checkpoint {
+    /// The fingerprint of the file when the transfer was started.
+    pub fingerprint: Fingerprint,
+    /// The amount of bytes of the file sent, which is an upper bound of what the receiver has.
+    pub offset: u64,
+}
+
+impl Checkpoint {
+    /// Returns whether the entire file has been sent.
+    #[inline]
+    pub fn is_complete(&self) -> bool {
+        self.offset == self.fingerprint.length
+    }
+
+    /// Returns the checkpoint at the amount of bytes the receiver reports to have received,
+    /// if that is less than the offset.
+    #[inline]
+    pub fn acknowledged(&self, received: u64) -> Checkpoint {
+        Checkpoint {
+            fingerprint: self.fingerprint,
+            offset: self.offset.min(received),
+        }
+    }
+
+    /// Reads a checkpoint stored using [`store()`], like [`ship::Checkpoint::load()`].
+    ///
+    /// [`store()`]: #method.store
+    /// [`ship::Checkpoint::load()`]: ../ship/struct.Checkpoint.html#method.load
+    #[inline]
+    pub fn load(path: &Path) -> io::Result<Option<Checkpoint>> {
+        checkpoint::load(path, decode)
+    }
+
+    /// Stores the checkpoint in a file, which is replaced atomically like by [`ship::Checkpoint::store()`].
+    ///
+    /// [`ship::Checkpoint::store()`]: ../ship/struct.Checkpoint.html#method.store
+    #[inline]
+    pub fn store(&self, path: &Path) -> io::Result<()> {
+        checkpoint::store(path, &encode(self))
+    }
+}
+
+/// Sends a file to a TCP stream, reporting the progress to `checkpoint` every `interval` bytes.
+///
+/// If `resume` is `None`, the entire file is sent.
+/// Otherwise the transfer continues at the offset of `resume`,
+/// after verifying that the file still has the same fingerprint.
+/// If it does not, an error of kind `InvalidData` is returned and nothing is sent.
+///
+/// A checkpoint is also reported when the transfer is complete,
+/// and before returning an error.
+/// If `checkpoint` returns an error, the transfer stops with this error.
+/// The final checkpoint is returned.
+///
+/// If the file is truncated during the transfer, an error of kind `UnexpectedEof` is returned.
+///
+/// # Panics
+///
+/// This panics if `interval` is `0`.
+///
+/// # Example
+///
+/// ```
+/// use snedfile::resume::{send_resumable, Checkpoint};
+/// # use std::io;
+/// # use std::fs::File;
+/// # use std::net::TcpStream;
+/// use std::path::Path;
+///
+/// fn upload(file: &File, stream: &mut TcpStream, state: &Path) -> io::Result<()> {
+///     let resume = Checkpoint::load(state)?;
+///
+///     // store the progress every 64 megabytes
+///     send_resumable(file, stream, resume.as_ref(), 64 * 1024 * 1024, |checkpoint| {
+///         checkpoint.store(state)
+///     })?;
+///
+///     Ok(())
+/// }
+/// ```
+pub fn send_resumable<F>(
+    file: &File,
+    stream: &mut TcpStream,
+    resume: Option<&Checkpoint>,
+    interval: u64,
+    mut checkpoint: F,
+) -> io::Result<Checkpoint>
+where
+    F: FnMut(&Checkpoint) -> io::Result<()>,
+{
+    assert!(interval > 0, "the checkpoint interval must not be 0");
+
+    let fingerprint = Fingerprint::of(file)?;
+
+    let mut progress = match resume {
+        Some(resume) if resume.fingerprint != fingerprint => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "file has changed since the checkpoint",
+            ));
+        }
+        Some(resume) if resume.offset > fingerprint.length => {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "checkpoint is past the end of the file",
+            ));
+        }
+        Some(resume) => *resume,
+        None => Checkpoint {
+            fingerprint,
+            offset: 0,
+        },
+    };
+
+    let length = fingerprint.length;
+    let mut next = progress.offset.saturating_add(interval).min(length);
+
+    while progress.offset < length {
+        let remaining = next - progress.offset;
+
+        let result = match crate::imp::send_chunk(file, stream, progress.offset, remaining) {
+            Ok(0) => Err(Error::new(
+                ErrorKind::UnexpectedEof,
+                "file was truncated while it was sent",
+            )),
+            Ok(n) => {
+                progress.offset += n;
+                Ok(())
+            }
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => wait::writable(stream),
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => Err(e),
+        };
+
+        if let Err(e) = result {
+            // the progress is reported even though the transfer failed, so that it can be resumed
+            let _ = checkpoint(&progress);
+
+            return Err(e);
+        };
+
+        if progress.offset == next && next < length {
+            checkpoint(&progress)?;
+            next = next.saturating_add(interval).min(length);
+        };
+    }
+
+    checkpoint(&progress)?;
+
+    Ok(progress)
+}
+
+fn encode(checkpoint: &Checkpoint) -> String {
+    let fingerprint = &checkpoint.fingerprint;
+
+    let modified = match fingerprint.modified {
+        Some(modified) => format!("{}.{:09}", modified.as_secs(), modified.subsec_nanos()),
+        None => String::from("-"),
+    };
+
+    format!(
+        "{} {} {} {} {}\n",
+        fingerprint.length, modified, fingerprint.device, fingerprint.inode, checkpoint.offset
+    )
+}
+
+fn decode(contents: &str) -> Option<Checkpoint> {
+    let mut fields = contents.split_whitespace();
+
+    let length = fields.next()?.parse().ok()?;
+
+    let modified = match fields.next()? {
+        "-" => None,
+        modified => {
+            let mut parts = modified.splitn(2, '.');
+            let secs = parts.next()?.parse().ok()?;
+            let nanos = parts.next()?.parse().ok()?;
+
+            if nanos >= 1_000_000_000 {
+                return None;
+            };
+
+            Some(Duration::new(secs, nanos))
+        }
+    };
+
+    let device = fields.next()?.parse().ok()?;
+    let inode = fields.next()?.parse().ok()?;
+    let offset = fields.next()?.parse().ok()?;
+
+    if fields.next().is_some() {
+        return None;
+    };
+
+    Some(Checkpoint {
+        fingerprint: Fingerprint {
+            length,
+            modified,
+            device,
+            inode,
+        },
+        offset,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encoding() {
+        let checkpoint = Checkpoint {
+            fingerprint: Fingerprint {
+                length: 13,
+                modified: Some(Duration::new(1_600_000_000, 5)),
+                device: 7,
+                inode: 42,
+            },
+            offset: 6,
+        };
+
+        assert_eq!(encode(&checkpoint), "13 1600000000.000000005 7 42 6\n");
+        assert_eq!(decode(&encode(&checkpoint)), Some(checkpoint));
+
+        assert_eq!(decode("13 - 7 42 6").unwrap().fingerprint.modified, None);
+        assert_eq!(decode("13 - 7 42"), None);
+        assert_eq!(decode("13 1.1000000000 7 42 6"), None);
+    }
+}
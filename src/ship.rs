@@ -0,0 +1,278 @@
+/*!
+Shipping a log file which is rotated while it is sent.
+
+Unlike [`follow()`], which sends whatever is appended to one open file,
+[`ship_log()`] watches a path.
+When the file at the path is replaced, for example by `logrotate` renaming it,
+the rest of the old file is sent before the new one is opened and sent from the start.
+When the file shrinks, for example by `logrotate` using `copytruncate`, it is sent from the start again.
+
+The position within the file is stored as a [`Checkpoint`] at most once per second while data is sent,
+and whenever the shipper stops or moves on to a new file,
+so that a restarted shipper resumes where the previous one stopped.
+The checkpoint is stored after the data has been sent,
+so if the process dies in between, the data sent since the last checkpoint is sent again.
+
+[`follow()`]: ../fn.follow.html
+[`ship_log()`]: fn.ship_log.html
+[`Checkpoint`]: struct.Checkpoint.html
+*/
+
+use crate::checkpoint::{self, file_id};
+use crate::follow::{self, Watcher};
+use crate::CancelToken;
+
+use std::fs::{self, File, Metadata};
+use std::io::{self, ErrorKind};
+use std::net::TcpStream;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// The shortest time between two checkpoints stored while data is sent.
+const STORE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The position of a shipper within a log file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Checkpoint {
+    /// The device containing the file, or `0` on platforms without inodes.
+    pub device: u64,
+    /// The inode of the file, or `0` on platforms without inodes.
+    pub inode: u64,
+    /// The amount of bytes of the file sent.
+    pub offset: u64,
+}
+
+impl Checkpoint {
+    /// Returns the start of the file.
+    #[inline]
+    fn start(metadata: &Metadata) -> Checkpoint {
+        let (device, inode) = file_id(metadata);
+
+        Checkpoint {
+            device,
+            inode,
+            offset: 0,
+        }
+    }
+
+    /// Reads a checkpoint stored using [`store()`].
+    ///
+    /// If the file does not exist, `None` is returned.
+    /// If it is malformed, an error of kind `InvalidData` is returned.
+    ///
+    /// [`store()`]: #method.store
+    pub fn load(path: &Path) -> io::Result<Option<Checkpoint>> {
+        checkpoint::load(path, |contents| {
+            let mut fields = contents.split_whitespace().map(str::parse);
+
+            match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some(Ok(device)), Some(Ok(inode)), Some(Ok(offset)), None) => Some(Checkpoint {
+                    device,
+                    inode,
+                    offset,
+                }),
+                _ => None,
+            }
+        })
+    }
+
+    /// Stores the checkpoint in a file.
+    ///
+    /// The checkpoint is written to a temporary file next to `path`, which then replaces `path`,
+    /// so that the previous checkpoint stays intact if the process dies while storing it.
+    #[inline]
+    pub fn store(&self, path: &Path) -> io::Result<()> {
+        checkpoint::store(
+            path,
+            &format!("{} {} {}\n", self.device, self.inode, self.offset),
+        )
+    }
+}
+
+/// Sends the log file at `log` to a TCP stream, following it across rotations.
+///
+/// The position is resumed from and stored to the checkpoint file at `checkpoint`.
+/// If the checkpoint refers to a different file than the one at `log`, or to an offset past its end,
+/// the file has been rotated in the meantime and is sent from the start.
+///
+/// This only returns once `cancel` is cancelled or an error occurs,
+/// for example because the peer has closed the connection.
+/// After a cancellation, the checkpoint is stored and the amount of bytes sent is returned.
+///
+/// # Example
+///
+/// ```
+/// use snedfile::ship::ship_log;
+/// # use std::io;
+/// # use std::net::TcpStream;
+/// use std::path::Path;
+/// use snedfile::CancelToken;
+///
+/// fn ship_access_log(stream: &mut TcpStream, shutdown: &CancelToken) -> io::Result<()> {
+///     ship_log(
+///         Path::new("/var/log/nginx/access.log"),
+///         stream,
+///         Path::new("/var/lib/shipper/access.checkpoint"),
+///         shutdown,
+///     )?;
+///
+///     Ok(())
+/// }
+/// ```
+pub fn ship_log(
+    log: &Path,
+    stream: &mut TcpStream,
+    checkpoint: &Path,
+    cancel: &CancelToken,
+) -> io::Result<u64> {
+    let mut file = File::open(log)?;
+    let mut position = Checkpoint::start(&file.metadata()?);
+
+    if let Some(stored) = Checkpoint::load(checkpoint)? {
+        if (stored.device, stored.inode) == (position.device, position.inode)
+            && stored.offset <= file.metadata()?.len()
+        {
+            position.offset = stored.offset;
+        };
+    };
+
+    let mut store = Store::new(checkpoint, position)?;
+    let mut watcher = Watcher::new(&file);
+    let mut sent = 0;
+
+    loop {
+        let length = file.metadata()?.len();
+
+        if length < position.offset {
+            position.offset = 0;
+        };
+
+        let start = position.offset;
+        let result = follow::send_range(&file, stream, &mut position.offset, length, cancel);
+        sent += position.offset - start;
+
+        if let Err(e) = result {
+            // the data sent so far is not sent again after a restart
+            let _ = store.now(position);
+
+            return Err(e);
+        };
+
+        if cancel.is_cancelled() {
+            store.now(position)?;
+
+            return Ok(sent);
+        };
+
+        store.throttled(position)?;
+
+        let replaced = match fs::metadata(log) {
+            Ok(metadata) => file_id(&metadata) != (position.device, position.inode),
+            // the file has been renamed, but the new one has not been created yet
+            Err(ref e) if e.kind() == ErrorKind::NotFound => false,
+            Err(e) => return Err(e),
+        };
+
+        if replaced {
+            // the old file may have been written to since its length was checked
+            let start = position.offset;
+            let length = file.metadata()?.len();
+            let result = follow::send_range(&file, stream, &mut position.offset, length, cancel);
+            sent += position.offset - start;
+
+            if let Err(e) = result {
+                let _ = store.now(position);
+
+                return Err(e);
+            };
+
+            if cancel.is_cancelled() {
+                store.now(position)?;
+
+                return Ok(sent);
+            };
+
+            // the file may be replaced again in between, so the identity is taken from the open file
+            file = File::open(log)?;
+            position = Checkpoint::start(&file.metadata()?);
+            store.now(position)?;
+            watcher = Watcher::new(&file);
+
+            continue;
+        };
+
+        watcher.wait(cancel)?;
+    }
+}
+
+/// Stores checkpoints, skipping those which are unchanged or follow the previous one too soon.
+struct Store<'a> {
+    path: &'a Path,
+    stored: Checkpoint,
+    time: Instant,
+}
+
+impl<'a> Store<'a> {
+    /// Stores the initial checkpoint.
+    fn new(path: &'a Path, checkpoint: Checkpoint) -> io::Result<Store<'a>> {
+        checkpoint.store(path)?;
+
+        Ok(Store {
+            path,
+            stored: checkpoint,
+            time: Instant::now(),
+        })
+    }
+
+    /// Stores the checkpoint if it has changed.
+    fn now(&mut self, checkpoint: Checkpoint) -> io::Result<()> {
+        if checkpoint != self.stored {
+            checkpoint.store(self.path)?;
+            self.stored = checkpoint;
+            self.time = Instant::now();
+        };
+
+        Ok(())
+    }
+
+    /// Stores the checkpoint if it has changed and the previous one is old enough.
+    ///
+    /// Since this is called at least every time the watcher times out,
+    /// a changed checkpoint is stored soon after the interval has passed.
+    #[inline]
+    fn throttled(&mut self, checkpoint: Checkpoint) -> io::Result<()> {
+        if self.time.elapsed() < STORE_INTERVAL {
+            return Ok(());
+        };
+
+        self.now(checkpoint)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checkpoint() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoint");
+
+        assert_eq!(Checkpoint::load(&path).unwrap(), None);
+
+        let checkpoint = Checkpoint {
+            device: 12,
+            inode: 1234,
+            offset: 5678,
+        };
+        checkpoint.store(&path).unwrap();
+
+        assert_eq!(Checkpoint::load(&path).unwrap(), Some(checkpoint));
+
+        fs::write(&path, "12 1234").unwrap();
+        assert_eq!(
+            Checkpoint::load(&path).unwrap_err().kind(),
+            ErrorKind::InvalidData
+        );
+    }
+}
@@ -0,0 +1,205 @@
+/*!
+Transferring data using `splice()` on Linux.
+
+Every transfer moves the data from its source into a pipe and from there into its sink,
+without copying it into userspace.
+Unlike `sendfile()`, this works for any combination of files, pipes and sockets,
+so it can be used for sources and sinks which `sendfile()` does not support.
+The intermediate pipes are taken from a pool of the current thread, and enlarged using `F_SETPIPE_SZ` if possible.
+
+[`send_file()`] has the same behavior as [`snedfile::send_file()`], so the two can be compared directly.
+
+This module is only available on Linux.
+
+[`send_file()`]: fn.send_file.html
+[`snedfile::send_file()`]: ../fn.send_file.html
+*/
+
+use crate::pipe::PooledPipe;
+use crate::wait;
+
+use libc::{c_uint, loff_t};
+use std::fs::File;
+use std::io::{self, Error, ErrorKind};
+use std::net::TcpStream;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::ptr;
+
+/// Sends the entire contents of a file using `splice()` instead of `sendfile()`.
+///
+/// The file is always sent starting at offset `0`, and the file offset is not changed.
+/// `WouldBlock` and `Interrupted` errors are handled.
+///
+/// # Example
+///
+/// ```
+/// # use std::io;
+/// # use std::fs::File;
+/// # use std::net::TcpStream;
+/// fn serve_static(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
+///     snedfile::splice::send_file(file, stream)
+/// }
+/// ```
+pub fn send_file(file: &mut File, stream: &mut TcpStream) -> io::Result<()> {
+    let length = file.metadata()?.len();
+
+    if length > loff_t::MAX as u64 {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "file exceeds maximum size",
+        ));
+    };
+
+    let mut pipe = PooledPipe::take()?;
+    let mut offset = 0;
+
+    while (offset as u64) < length {
+        let chunk = (length - offset as u64).min(pipe.capacity() as u64) as usize;
+
+        match fill(&mut pipe, file.as_raw_fd(), Some(&mut offset), chunk) {
+            Ok(0) => break, // the file has been truncated
+            Ok(_) => drain_with(&mut pipe, stream.as_raw_fd(), (offset as u64) < length)?,
+            Err(ref e) if check_error(e.kind()) => continue,
+            Err(e) => return Err(e),
+        };
+    }
+
+    Ok(())
+}
+
+/// Moves all data from `source` to `sink` until `source` reaches the end, and returns the amount of bytes moved.
+///
+/// Both can be any file descriptor `splice()` supports, for example files, pipes and sockets.
+/// Files are read and written at their current offset, which is advanced.
+/// `WouldBlock` and `Interrupted` errors are handled.
+///
+/// # Example
+///
+/// ```
+/// # use std::io;
+/// use std::net::TcpStream;
+/// use std::process::ChildStdout;
+///
+/// fn forward_output(output: &ChildStdout, stream: &TcpStream) -> io::Result<u64> {
+///     snedfile::splice::copy(output, stream)
+/// }
+/// ```
+pub fn copy<R: AsRawFd + ?Sized, W: AsRawFd + ?Sized>(source: &R, sink: &W) -> io::Result<u64> {
+    let mut pipe = PooledPipe::take()?;
+
+    copy_through(&mut pipe, source.as_raw_fd(), sink.as_raw_fd())
+}
+
+/// Like [`copy()`], but moves the data through the given pipe.
+///
+/// [`copy()`]: fn.copy.html
+pub(crate) fn copy_through(pipe: &mut PooledPipe, source: RawFd, sink: RawFd) -> io::Result<u64> {
+    let mut moved = 0;
+
+    loop {
+        let chunk = pipe.capacity();
+
+        match fill(pipe, source, None, chunk) {
+            Ok(0) => return Ok(moved),
+            Ok(filled) => {
+                // a partially filled pipe means that the source has nothing more to read for now
+                drain_with(pipe, sink, filled == chunk)?;
+                moved += filled as u64;
+            }
+            Err(ref e) if check_error(e.kind()) => continue,
+            Err(e) => return Err(e),
+        };
+    }
+}
+
+/// Splices at most `length` bytes from `source` into the pipe, at `offset` if given.
+pub(crate) fn fill(
+    pipe: &mut PooledPipe,
+    source: RawFd,
+    offset: Option<&mut loff_t>,
+    length: usize,
+) -> io::Result<usize> {
+    let offset = match offset {
+        Some(offset) => offset as *mut loff_t,
+        None => ptr::null_mut(),
+    };
+
+    match unsafe {
+        libc::splice(
+            source,
+            offset,
+            pipe.write(),
+            ptr::null_mut(),
+            length,
+            libc::SPLICE_F_MOVE | libc::SPLICE_F_MORE,
+        )
+    } {
+        -1 => Err(Error::last_os_error()),
+        filled => {
+            pipe.buffered += filled as usize;
+
+            Ok(filled as usize)
+        }
+    }
+}
+
+/// Splices everything buffered in the pipe into `sink`.
+#[inline]
+pub(crate) fn drain(pipe: &mut PooledPipe, sink: RawFd) -> io::Result<()> {
+    drain_with(pipe, sink, false)
+}
+
+/// Like [`drain()`], but if `more` is `true`, more data follows,
+/// so a socket may wait for it before sending a partial segment.
+///
+/// [`drain()`]: fn.drain.html
+fn drain_with(pipe: &mut PooledPipe, sink: RawFd, more: bool) -> io::Result<()> {
+    let flags = if more {
+        libc::SPLICE_F_MOVE | libc::SPLICE_F_MORE
+    } else {
+        libc::SPLICE_F_MOVE
+    };
+
+    while pipe.buffered > 0 {
+        match splice_out(pipe, sink, pipe.buffered, flags) {
+            Ok(0) => return Err(Error::from(ErrorKind::WriteZero)),
+            Ok(_) => (),
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => wait::writable_fd(sink)?,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+    }
+
+    Ok(())
+}
+
+/// Splices at most `length` bytes from the pipe into `sink` once.
+pub(crate) fn splice_out(
+    pipe: &mut PooledPipe,
+    sink: RawFd,
+    length: usize,
+    flags: c_uint,
+) -> io::Result<usize> {
+    match unsafe {
+        libc::splice(
+            pipe.read(),
+            ptr::null_mut(),
+            sink,
+            ptr::null_mut(),
+            length,
+            flags,
+        )
+    } {
+        -1 => Err(Error::last_os_error()),
+        moved => {
+            pipe.buffered -= moved as usize;
+
+            Ok(moved as usize)
+        }
+    }
+}
+
+#[inline]
+fn check_error(e: ErrorKind) -> bool {
+    e == ErrorKind::WouldBlock || e == ErrorKind::Interrupted
+}
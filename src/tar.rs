@@ -0,0 +1,372 @@
+/*!
+Streaming files as a tar archive.
+
+The headers are written from userspace,
+while the contents of the files are sent like using [`send_exact()`],
+so that they are not copied into userspace where the platform supports it.
+
+Entries use the ustar format.
+Names longer than ustar supports and files of 8 gigabytes or more
+are described using PAX extended headers, which every modern `tar` understands.
+
+[`send_exact()`]: ../fn.send_exact.html
+*/
+
+use crate::wait;
+
+use std::fs::File;
+use std::io::{self, Error, ErrorKind, Write};
+use std::net::TcpStream;
+use std::path::{Component, Path};
+use std::time::UNIX_EPOCH;
+
+const BLOCK: usize = 512;
+
+/// The information stored in the header of an entry.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Entry {
+    /// The path of the entry inside of the archive, using `/` as separator.
+    pub name: String,
+    /// The permission bits.
+    pub mode: u32,
+    /// The modification time in seconds since the Unix epoch.
+    pub mtime: u64,
+}
+
+impl Entry {
+    /// Creates an entry with the mode `0o644` and the modification time `0`.
+    #[inline]
+    pub fn new(name: &str) -> Entry {
+        Entry {
+            name: String::from(name),
+            mode: 0o644,
+            mtime: 0,
+        }
+    }
+}
+
+/// Writes a tar archive to a TCP stream.
+///
+/// The archive is complete once [`finish()`] has been called.
+///
+/// # Example
+///
+/// ```
+/// use snedfile::tar::TarWriter;
+/// # use std::io;
+/// # use std::net::TcpStream;
+/// use std::path::Path;
+///
+/// fn download_bundle(stream: &mut TcpStream, paths: &[&Path]) -> io::Result<()> {
+///     let mut archive = TarWriter::new(stream);
+///
+///     for path in paths {
+///         archive.append_path(path)?;
+///     }
+///
+///     archive.finish()
+/// }
+/// ```
+///
+/// [`finish()`]: #method.finish
+#[derive(Debug)]
+pub struct TarWriter<'a> {
+    stream: &'a mut TcpStream,
+    written: u64,
+}
+
+impl<'a> TarWriter<'a> {
+    /// Creates a writer for an empty archive.
+    #[inline]
+    pub fn new(stream: &'a mut TcpStream) -> TarWriter<'a> {
+        TarWriter { stream, written: 0 }
+    }
+
+    /// Appends the regular file at `path`.
+    ///
+    /// The name of the entry is the path without its root,
+    /// and the mode and modification time are taken from the file.
+    /// The amount of bytes of the file is returned.
+    ///
+    /// Paths containing `..` are rejected with an error of kind `InvalidInput`,
+    /// since the entry would be extracted outside of the target directory.
+    pub fn append_path(&mut self, path: &Path) -> io::Result<u64> {
+        if path
+            .components()
+            .any(|component| component == Component::ParentDir)
+        {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "paths containing `..` are not supported",
+            ));
+        };
+
+        let mut file = File::open(path)?;
+        let metadata = file.metadata()?;
+
+        if !metadata.is_file() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "only regular files are supported",
+            ));
+        };
+
+        let name = path
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(part) => Some(part.to_string_lossy()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let entry = Entry {
+            name,
+            mode: mode(&metadata),
+            mtime: metadata
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|time| time.as_secs())
+                .unwrap_or(0),
+        };
+
+        self.append_file(&entry, &mut file)
+    }
+
+    /// Appends the entire contents of a file as `entry`.
+    ///
+    /// The file is always sent starting at offset `0`.
+    /// The amount of bytes of the file is returned.
+    ///
+    /// If the file is truncated while it is sent, an error of kind `UnexpectedEof` is returned,
+    /// and the archive is broken.
+    pub fn append_file(&mut self, entry: &Entry, file: &mut File) -> io::Result<u64> {
+        let size = file.metadata()?.len();
+
+        let header = header(entry, size)?;
+        self.write(&header)?;
+
+        let mut sent = 0;
+
+        while sent < size {
+            match crate::imp::send_chunk(file, self.stream, sent, size - sent) {
+                Ok(0) => {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "file was truncated while it was sent",
+                    ));
+                }
+                Ok(n) => {
+                    sent += n;
+                    self.written += n;
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => wait::writable(self.stream)?,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+        }
+
+        self.write(&[0; BLOCK][..padding(size)])?;
+
+        Ok(size)
+    }
+
+    /// Returns the amount of bytes of the archive written so far.
+    ///
+    /// After an error, this includes the bytes written before it, so it is the length of the broken archive.
+    #[inline]
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Writes the end of the archive.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.write(&[0; 2 * BLOCK])
+    }
+
+    fn write(&mut self, mut buf: &[u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            match self.stream.write(buf) {
+                Ok(0) => return Err(Error::from(ErrorKind::WriteZero)),
+                Ok(n) => {
+                    buf = &buf[n..];
+                    self.written += n as u64;
+                }
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => wait::writable(self.stream)?,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+        }
+
+        Ok(())
+    }
+}
+
+/// Encodes the headers of an entry, including a PAX extended header if necessary.
+fn header(entry: &Entry, size: u64) -> io::Result<Vec<u8>> {
+    if entry.name.is_empty() || entry.name.contains('\0') {
+        return Err(Error::new(ErrorKind::InvalidInput, "invalid entry name"));
+    };
+
+    let mut records = Vec::new();
+
+    let (prefix, name) = match split_name(&entry.name) {
+        Some(split) => split,
+        None => {
+            pax_record(&mut records, "path", &entry.name);
+            ("", truncate(&entry.name, 100))
+        }
+    };
+
+    // the numeric fields have room for 11 octal digits
+    const MAX_OCTAL: u64 = 0o77777777777;
+
+    if size > MAX_OCTAL {
+        pax_record(&mut records, "size", &size.to_string());
+    };
+
+    if entry.mtime > MAX_OCTAL {
+        pax_record(&mut records, "mtime", &entry.mtime.to_string());
+    };
+
+    let mut buf = Vec::with_capacity(BLOCK);
+
+    if !records.is_empty() {
+        let pax_name = format!("PaxHeaders/{}", truncate(name, 89));
+
+        buf.extend_from_slice(&ustar("", &pax_name, 0o644, records.len() as u64, 0, b'x'));
+        buf.extend_from_slice(&records);
+        buf.resize(buf.len() + padding(records.len() as u64), 0);
+    };
+
+    buf.extend_from_slice(&ustar(
+        prefix,
+        name,
+        entry.mode & 0o7777,
+        size.min(MAX_OCTAL),
+        entry.mtime.min(MAX_OCTAL),
+        b'0',
+    ));
+
+    Ok(buf)
+}
+
+/// Encodes one ustar header block.
+fn ustar(prefix: &str, name: &str, mode: u32, size: u64, mtime: u64, kind: u8) -> [u8; BLOCK] {
+    let mut block = [0; BLOCK];
+
+    block[0..name.len()].copy_from_slice(name.as_bytes());
+    octal(&mut block[100..108], mode as u64);
+    octal(&mut block[108..116], 0); // uid
+    octal(&mut block[116..124], 0); // gid
+    octal(&mut block[124..136], size);
+    octal(&mut block[136..148], mtime);
+    block[156] = kind;
+    block[257..263].copy_from_slice(b"ustar\0");
+    block[263..265].copy_from_slice(b"00");
+    block[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+    // the checksum is computed with the checksum field filled with spaces
+    block[148..156].copy_from_slice(b"        ");
+    let checksum: u32 = block.iter().map(|&byte| byte as u32).sum();
+    octal(&mut block[148..155], checksum as u64);
+
+    block
+}
+
+/// Writes `value` as zero-padded octal number terminated by NUL.
+fn octal(field: &mut [u8], value: u64) {
+    let digits = format!("{:0width$o}", value, width = field.len() - 1);
+    field[..digits.len()].copy_from_slice(digits.as_bytes());
+    field[digits.len()] = 0;
+}
+
+/// Splits a name into the prefix and name fields of ustar, if it fits.
+fn split_name(name: &str) -> Option<(&str, &str)> {
+    if name.len() <= 100 {
+        return Some(("", name));
+    };
+
+    // the prefix is joined with the name using a `/`
+    name.match_indices('/')
+        .map(|(index, _)| (&name[..index], &name[index + 1..]))
+        .find(|(prefix, name)| prefix.len() <= 155 && !name.is_empty() && name.len() <= 100)
+}
+
+/// Returns at most `max` bytes of the name, without splitting a character.
+fn truncate(name: &str, max: usize) -> &str {
+    let mut end = name.len().min(max);
+
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    &name[..end]
+}
+
+/// Appends a PAX record, which starts with its own length in decimal.
+fn pax_record(records: &mut Vec<u8>, key: &str, value: &str) {
+    // the length of ` key=value\n`
+    let rest = key.len() + value.len() + 3;
+
+    let mut length = rest + 1;
+
+    while length != rest + length.to_string().len() {
+        length = rest + length.to_string().len();
+    }
+
+    records.extend_from_slice(format!("{} {}={}\n", length, key, value).as_bytes());
+}
+
+/// Returns the amount of zeros following `size` bytes to fill the last block.
+#[inline]
+fn padding(size: u64) -> usize {
+    ((BLOCK as u64 - size % BLOCK as u64) % BLOCK as u64) as usize
+}
+
+#[cfg(unix)]
+#[inline]
+fn mode(metadata: &std::fs::Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.permissions().mode()
+}
+
+#[cfg(not(unix))]
+#[inline]
+fn mode(metadata: &std::fs::Metadata) -> u32 {
+    if metadata.permissions().readonly() {
+        0o444
+    } else {
+        0o644
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pax_records() {
+        let mut records = Vec::new();
+
+        pax_record(&mut records, "size", "8589934592");
+        assert_eq!(records, b"19 size=8589934592\n");
+
+        records.clear();
+        pax_record(&mut records, "path", "abc");
+        assert_eq!(records, b"12 path=abc\n");
+    }
+
+    #[test]
+    fn large_file() {
+        let header = header(&Entry::new("large"), 8 * 1024 * 1024 * 1024).unwrap();
+
+        // the extended header, its records padded to a block, and the ustar header
+        assert_eq!(header.len(), 3 * BLOCK);
+        assert_eq!(header[156], b'x');
+        assert!(header[BLOCK..].starts_with(b"19 size=8589934592\n"));
+        assert_eq!(&header[2 * BLOCK..2 * BLOCK + 5], b"large");
+    }
+}
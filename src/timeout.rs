@@ -0,0 +1,65 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::time::{Duration, Instant};
+
+/// The time limits of [`send_file_with_timeouts()`].
+///
+/// [`send_file_with_timeouts()`]: fn.send_file_with_timeouts.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Timeouts {
+    /// The point in time by which the entire transfer must have completed.
+    pub deadline: Option<Instant>,
+    /// The longest time without any progress.
+    pub idle: Option<Duration>,
+}
+
+impl Timeouts {
+    /// Creates timeouts with a deadline `duration` from now and no idle timeout.
+    #[inline]
+    pub fn total(duration: Duration) -> Timeouts {
+        Timeouts {
+            deadline: Some(Instant::now() + duration),
+            idle: None,
+        }
+    }
+}
+
+/// The error of a transfer which exceeded one of its [`Timeouts`].
+///
+/// It is returned wrapped in an `io::Error` of kind `TimedOut`,
+/// and can be recovered using `get_ref()` and `downcast_ref()`.
+///
+/// [`Timeouts`]: struct.Timeouts.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TimedOut {
+    /// The amount of bytes sent before the transfer timed out.
+    pub sent: u64,
+    /// Whether the idle timeout expired, rather than the deadline.
+    pub idle: bool,
+}
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = if self.idle {
+            "no progress"
+        } else {
+            "deadline passed"
+        };
+
+        write!(
+            f,
+            "transfer timed out after {} bytes: {}",
+            self.sent, reason
+        )
+    }
+}
+
+impl Error for TimedOut {}
+
+impl From<TimedOut> for io::Error {
+    #[inline]
+    fn from(e: TimedOut) -> io::Error {
+        io::Error::new(ErrorKind::TimedOut, e)
+    }
+}
@@ -0,0 +1,270 @@
+/*!
+Sending files to [`tokio`] TCP streams.
+
+This module is only available with the `tokio` feature.
+
+[`tokio`]: https://docs.rs/tokio
+*/
+
+use ::tokio::net::TcpStream;
+
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::mem::ManuallyDrop;
+
+/// Files which can be sent using [`send_file()`], which are `std::fs::File` and `tokio::fs::File`.
+///
+/// This trait is sealed and cannot be implemented outside of this crate.
+///
+/// [`send_file()`]: fn.send_file.html
+pub trait SendFile: private::Sealed {}
+
+impl SendFile for File {}
+
+impl SendFile for ::tokio::fs::File {}
+
+mod private {
+    use std::fs::File;
+    use std::mem::ManuallyDrop;
+
+    pub trait Sealed {
+        /// Borrows the underlying descriptor as a `std::fs::File`, which must not be dropped.
+        fn borrow_std(&self) -> ManuallyDrop<File>;
+    }
+
+    impl Sealed for File {
+        #[inline]
+        fn borrow_std(&self) -> ManuallyDrop<File> {
+            borrow(self)
+        }
+    }
+
+    impl Sealed for ::tokio::fs::File {
+        #[inline]
+        fn borrow_std(&self) -> ManuallyDrop<File> {
+            borrow(self)
+        }
+    }
+
+    #[cfg(unix)]
+    #[inline]
+    fn borrow<T: std::os::unix::io::AsRawFd>(file: &T) -> ManuallyDrop<File> {
+        use std::os::unix::io::FromRawFd;
+
+        ManuallyDrop::new(unsafe { File::from_raw_fd(file.as_raw_fd()) })
+    }
+
+    #[cfg(windows)]
+    #[inline]
+    fn borrow<T: std::os::windows::io::AsRawHandle>(file: &T) -> ManuallyDrop<File> {
+        use std::os::windows::io::FromRawHandle;
+
+        ManuallyDrop::new(unsafe { File::from_raw_handle(file.as_raw_handle()) })
+    }
+}
+
+/// Sends the entire contents of a file to a `tokio` TCP stream.
+///
+/// This is the asynchronous equivalent of [`snedfile::send_file()`].
+/// On platforms with a native `sendfile()`,
+/// it is called whenever the stream is writable instead of spinning on `WouldBlock` errors,
+/// so that the runtime can drive other tasks in the meantime.
+/// The fallback, which is also used if the native `sendfile()` does not support the file,
+/// reads the file in a single task on the blocking thread pool of the runtime using [`spawn_blocking()`],
+/// which requires a `tokio` runtime.
+///
+/// Unlike `send_file()`, the file is always sent starting at offset `0`,
+/// and the file offset is not changed, except on Windows, where reading the file for the fallback moves it.
+/// Both a `std::fs::File` and a `tokio::fs::File` can be sent,
+/// but writes to a `tokio::fs::File` which have not been flushed yet are not.
+///
+/// # Example
+///
+/// ```
+/// # use std::io;
+/// use tokio::fs::File;
+/// use tokio::net::TcpStream;
+///
+/// async fn serve_static(file: &File, stream: &TcpStream) -> io::Result<()> {
+///     snedfile::tokio::send_file(file, stream).await
+/// }
+/// ```
+///
+/// [`snedfile::send_file()`]: ../fn.send_file.html
+/// [`spawn_blocking()`]: https://docs.rs/tokio/1/tokio/task/fn.spawn_blocking.html
+#[inline]
+pub async fn send_file<F: SendFile>(file: &F, stream: &TcpStream) -> io::Result<()> {
+    let file = file.borrow_std();
+    let length = file.metadata()?.len();
+
+    send_file_imp(&file, stream, length).await
+}
+
+#[cfg(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    all(target_os = "ios", feature = "ios-sendfile"),
+    target_os = "freebsd",
+    target_os = "dragonfly"
+))]
+async fn send_file_imp(file: &File, stream: &TcpStream, length: u64) -> io::Result<()> {
+    use ::tokio::io::Interest;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    // the native implementations only need the descriptor,
+    // so the stream is borrowed without taking ownership of it
+    let std_stream =
+        ManuallyDrop::new(unsafe { std::net::TcpStream::from_raw_fd(stream.as_raw_fd()) });
+
+    let mut offset = 0;
+
+    while offset < length {
+        stream.writable().await?;
+
+        match stream.try_io(Interest::WRITABLE, || {
+            crate::imp::try_send_chunk(file, &std_stream, offset, length - offset)
+        }) {
+            Ok(Some(0)) => break, // the file has been truncated
+            Ok(Some(sent)) => offset += sent,
+            // reading the file blocks, so it must not happen on the runtime
+            Ok(None) => return send_blocking(file, stream, offset, length).await,
+            Err(ref e) if check_error(e.kind()) => continue,
+            Err(e) => return Err(e),
+        };
+    }
+
+    Ok(())
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    all(target_os = "ios", feature = "ios-sendfile"),
+    target_os = "freebsd",
+    target_os = "dragonfly"
+)))]
+#[inline]
+async fn send_file_imp(file: &File, stream: &TcpStream, length: u64) -> io::Result<()> {
+    send_blocking(file, stream, 0, length).await
+}
+
+/// A buffer and the amount of bytes read into it.
+type Chunk = io::Result<(Vec<u8>, usize)>;
+
+/// Sends the file from `offset` up to `length`, reading it on the blocking thread pool.
+async fn send_blocking(
+    file: &File,
+    stream: &TcpStream,
+    offset: u64,
+    length: u64,
+) -> io::Result<()> {
+    use crate::BufferPool;
+    use ::tokio::sync::mpsc;
+    use ::tokio::task;
+
+    // a single blocking task reads the next buffer while the previous one is written
+    let (sender, mut receiver) = mpsc::channel(1);
+    let file = file.try_clone()?;
+    let reader = task::spawn_blocking(move || read_ahead(&file, offset, length, &sender));
+
+    while let Some(chunk) = receiver.recv().await {
+        let (buf, read) = chunk?;
+        let mut written = 0;
+
+        while written < read {
+            stream.writable().await?;
+
+            match stream.try_write(&buf[written..read]) {
+                Ok(0) => return Err(io::Error::from(ErrorKind::WriteZero)),
+                Ok(sent) => written += sent,
+                Err(ref e) if check_error(e.kind()) => continue,
+                Err(e) => return Err(e),
+            };
+        }
+
+        BufferPool::global().give(buf);
+    }
+
+    reader.await.map_err(io::Error::other)
+}
+
+/// Reads the file from `offset` up to `length` into buffers of the global pool,
+/// until the end of the file, an error, or until the receiver is gone.
+fn read_ahead(
+    file: &File,
+    mut offset: u64,
+    length: u64,
+    sender: &::tokio::sync::mpsc::Sender<Chunk>,
+) {
+    let pool = crate::BufferPool::global();
+
+    while offset < length {
+        let mut buf = pool.take();
+
+        let read = loop {
+            match crate::fallback::read_at(file, &mut buf, offset) {
+                Ok(read) => break read,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    pool.give(buf);
+                    let _ = sender.blocking_send(Err(e));
+
+                    return;
+                }
+            };
+        };
+
+        if read == 0 {
+            pool.give(buf);
+
+            return; // the file has been truncated
+        };
+
+        let read = read.min((length - offset).min(usize::MAX as u64) as usize);
+        offset += read as u64;
+
+        if sender.blocking_send(Ok((buf, read))).is_err() {
+            return; // the transfer has failed or been dropped
+        };
+    }
+}
+
+#[inline]
+fn check_error(e: ErrorKind) -> bool {
+    e == ErrorKind::WouldBlock || e == ErrorKind::Interrupted
+}
+
+#[cfg(test)]
+mod tests {
+    use ::tokio::net::TcpStream;
+    use std::io::{Read, Write};
+
+    #[::tokio::test]
+    async fn send_blocking() {
+        let data: Vec<u8> = (0..4 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(&data).unwrap();
+
+        let (local, mut remote) = tcp_test::channel();
+
+        local.set_nonblocking(true).unwrap();
+        let local = TcpStream::from_std(local).unwrap();
+
+        let reader = std::thread::spawn(move || {
+            let mut received = Vec::new();
+            remote.read_to_end(&mut received).unwrap();
+            received
+        });
+
+        let length = data.len() as u64 - 1000;
+        super::send_blocking(&file, &local, 1000, length)
+            .await
+            .unwrap();
+        drop(local);
+
+        assert!(reader.join().unwrap() == data[1000..length as usize]);
+    }
+}
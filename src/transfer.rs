@@ -0,0 +1,182 @@
+use crate::progress::{Aborted, Control, Granularity};
+use crate::timeout::{TimedOut, Timeouts};
+use crate::{cancel, wait, CancelToken};
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, ErrorKind};
+use std::net::TcpStream;
+use std::time::Instant;
+
+/// The options of [`send_file_with_options()`], which can be combined freely.
+///
+/// # Example
+///
+/// ```
+/// use snedfile::{send_file_with_options, CancelToken, Control, SendOptions, Timeouts};
+/// # use std::io;
+/// # use std::fs::File;
+/// # use std::net::TcpStream;
+/// use std::time::Duration;
+///
+/// fn serve(file: &File, stream: &mut TcpStream, shutdown: &CancelToken) -> io::Result<u64> {
+///     let mut report = |sent, _total| {
+///         println!("{} bytes sent", sent);
+///         Control::Continue
+///     };
+///
+///     let options = SendOptions {
+///         cancel: Some(shutdown),
+///         timeouts: Timeouts::total(Duration::from_secs(600)),
+///         progress: Some(&mut report),
+///         ..SendOptions::default()
+///     };
+///
+///     send_file_with_options(file, stream, options)
+/// }
+/// ```
+///
+/// [`send_file_with_options()`]: fn.send_file_with_options.html
+pub struct SendOptions<'a> {
+    /// Stops the transfer with a [`Cancelled`] error once cancelled.
+    ///
+    /// [`Cancelled`]: struct.Cancelled.html
+    pub cancel: Option<&'a CancelToken>,
+    /// Stops the transfer with a [`TimedOut`] error once exceeded.
+    ///
+    /// [`TimedOut`]: struct.TimedOut.html
+    pub timeouts: Timeouts,
+    /// How often `progress` is invoked, which is [`Granularity::Chunk`] by default.
+    ///
+    /// [`Granularity::Chunk`]: enum.Granularity.html#variant.Chunk
+    pub granularity: Granularity,
+    /// Receives the amount of bytes sent so far and the total length of the file,
+    /// and stops the transfer with an [`Aborted`] error if it returns [`Control::Abort`].
+    ///
+    /// [`Aborted`]: struct.Aborted.html
+    /// [`Control::Abort`]: enum.Control.html#variant.Abort
+    pub progress: Option<&'a mut dyn FnMut(u64, Option<u64>) -> Control>,
+}
+
+impl Default for SendOptions<'_> {
+    fn default() -> Self {
+        SendOptions {
+            cancel: None,
+            timeouts: Timeouts::default(),
+            granularity: Granularity::Chunk,
+            progress: None,
+        }
+    }
+}
+
+impl fmt::Debug for SendOptions<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SendOptions")
+            .field("cancel", &self.cancel)
+            .field("timeouts", &self.timeouts)
+            .field("granularity", &self.granularity)
+            .field("progress", &self.progress.is_some())
+            .finish()
+    }
+}
+
+pub fn send_file(file: &File, stream: &TcpStream, options: SendOptions) -> io::Result<u64> {
+    if options.timeouts == Timeouts::default() {
+        return send(file, stream, options);
+    };
+
+    // a blocking stream is limited by its write timeout instead of `poll()`
+    let write_timeout = stream.write_timeout()?;
+
+    let result = send(file, stream, options);
+    let restored = stream.set_write_timeout(write_timeout);
+
+    let sent = result?;
+    restored?;
+
+    Ok(sent)
+}
+
+fn send(file: &File, stream: &TcpStream, mut options: SendOptions) -> io::Result<u64> {
+    let length = file.metadata()?.len();
+    let timeouts = options.timeouts;
+
+    let step = match options.granularity {
+        Granularity::Chunk => 1,
+        Granularity::Bytes(step) => step.max(1),
+    };
+
+    // bounded system calls notice a cancellation and invoke the callback often enough
+    let bounded = options.cancel.is_some() || options.progress.is_some();
+
+    let mut offset = 0;
+    let mut reported = 0;
+    let mut progress = Instant::now();
+
+    while offset < length {
+        if let Some(cancel) = options.cancel {
+            cancel.check(offset)?;
+        };
+
+        let idle = timeouts.idle.map(|idle| progress + idle);
+
+        let expiry = match (timeouts.deadline, idle) {
+            (Some(deadline), Some(idle)) => Some(deadline.min(idle)),
+            (deadline, idle) => deadline.or(idle),
+        };
+
+        if let Some(expiry) = expiry {
+            let now = Instant::now();
+
+            if now >= expiry {
+                return Err(TimedOut {
+                    sent: offset,
+                    idle: idle == Some(expiry),
+                }
+                .into());
+            };
+
+            stream.set_write_timeout(Some(expiry - now))?;
+        };
+
+        let result = if bounded {
+            cancel::send_chunk(file, stream, offset, length - offset)
+        } else {
+            crate::imp::send_chunk(file, stream, offset, length - offset)
+        };
+
+        match result {
+            Ok(0) => break, // the file has been truncated
+            Ok(n) => {
+                offset += n;
+                progress = Instant::now();
+            }
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                wait::writable_until(stream, options.cancel, expiry)?;
+                continue;
+            }
+            // an expired write timeout is reported as `WouldBlock` or `TimedOut` depending on the platform
+            Err(ref e) if e.kind() == ErrorKind::TimedOut => continue,
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        // the last invocation happens after the loop
+        if let Some(ref mut callback) = options.progress {
+            if offset - reported >= step && offset < length {
+                reported = offset;
+
+                if callback(offset, Some(length)) == Control::Abort {
+                    return Err(Aborted { sent: offset }.into());
+                };
+            };
+        };
+    }
+
+    // the transfer is complete, so it cannot be aborted anymore
+    if let Some(callback) = options.progress {
+        callback(offset, Some(length));
+    };
+
+    Ok(offset)
+}
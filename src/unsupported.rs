@@ -0,0 +1,208 @@
+//! Detection and bookkeeping of file and stream pairs the native `sendfile()` cannot handle.
+
+use libc::c_int;
+use std::fs::File;
+use std::io::Error;
+use std::mem;
+use std::net::TcpStream;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::RwLock;
+
+/// The errors `sendfile()` returns if the source or sink type is not supported.
+const ERRORS: [c_int; 4] = [libc::EINVAL, libc::ENOSYS, libc::EOPNOTSUPP, libc::ENOTSUP];
+
+/// A device and inode number pair.
+///
+/// Unlike a bare file descriptor, this identifies the open file and not the descriptor slot,
+/// which is reused as soon as it is closed.
+type Id = (u64, u64);
+
+/// A file and stream pair on which the native `sendfile()` failed.
+struct Entry {
+    fds: (RawFd, RawFd),
+    ids: (Id, Id),
+}
+
+/// The most pairs remembered, after which the oldest ones are forgotten.
+const MAX_ENTRIES: usize = 64;
+
+static UNSUPPORTED: RwLock<Vec<Entry>> = RwLock::new(Vec::new());
+
+/// The amount of entries, so that the common case of no unsupported pairs does not take the lock.
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns `true` if the error indicates that `sendfile()` is unsupported for the descriptors.
+///
+/// This is only meaningful if no bytes have been sent yet,
+/// because `EINVAL` is also used for other invalid arguments.
+#[inline]
+pub fn check(e: &Error) -> bool {
+    match e.raw_os_error() {
+        Some(code) => ERRORS.contains(&code),
+        None => false,
+    }
+}
+
+/// Returns `true` if a previous call recorded that the native `sendfile()` fails on this pair.
+///
+/// Unless a pair has been recorded, this does not use any system calls or locks.
+pub fn is_unsupported(file: &File, stream: &TcpStream) -> bool {
+    if COUNT.load(Ordering::Relaxed) == 0 {
+        return false;
+    };
+
+    let fds = (file.as_raw_fd(), stream.as_raw_fd());
+
+    let ids = match UNSUPPORTED.read() {
+        Ok(entries) => match entries.iter().find(|entry| entry.fds == fds) {
+            Some(entry) => entry.ids,
+            None => return false,
+        },
+        Err(_) => return false,
+    };
+
+    // the descriptors may have been closed and reused for other files since
+    if key(file, stream) == Some(ids) {
+        true
+    } else {
+        if let Ok(mut entries) = UNSUPPORTED.write() {
+            entries.retain(|entry| entry.fds != fds);
+            COUNT.store(entries.len(), Ordering::Relaxed);
+        };
+
+        false
+    }
+}
+
+/// Records that the native `sendfile()` fails on this pair so later calls skip it.
+///
+/// This is only called after `sendfile()` failed, so the cost does not matter.
+pub fn mark_unsupported(file: &File, stream: &TcpStream) {
+    let ids = match key(file, stream) {
+        Some(ids) => ids,
+        None => return,
+    };
+
+    let fds = (file.as_raw_fd(), stream.as_raw_fd());
+
+    if let Ok(mut entries) = UNSUPPORTED.write() {
+        entries.retain(|entry| entry.fds != fds);
+
+        if entries.len() >= MAX_ENTRIES {
+            entries.remove(0);
+        };
+
+        entries.push(Entry { fds, ids });
+        COUNT.store(entries.len(), Ordering::Relaxed);
+    };
+}
+
+fn key(file: &File, stream: &TcpStream) -> Option<(Id, Id)> {
+    Some((id(file.as_raw_fd())?, id(stream.as_raw_fd())?))
+}
+
+fn id(fd: RawFd) -> Option<Id> {
+    let mut stat: libc::stat = unsafe { mem::zeroed() };
+
+    if unsafe { libc::fstat(fd, &mut stat) } == -1 {
+        None
+    } else {
+        Some((stat.st_dev as u64, stat.st_ino as u64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::sync::{Mutex, MutexGuard};
+
+    /// The recorded pairs are shared by the whole process, so the tests recording them take turns.
+    fn lock() -> MutexGuard<'static, ()> {
+        static LOCK: Mutex<()> = Mutex::new(());
+
+        let guard = LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        UNSUPPORTED.write().unwrap().clear();
+        COUNT.store(0, Ordering::Relaxed);
+
+        guard
+    }
+
+    #[test]
+    fn errors() {
+        assert!(check(&Error::from_raw_os_error(libc::EINVAL)));
+        assert!(check(&Error::from_raw_os_error(libc::EOPNOTSUPP)));
+        assert!(!check(&Error::from_raw_os_error(libc::EAGAIN)));
+        assert!(!check(&Error::other("not an OS error")));
+    }
+
+    #[test]
+    fn record() {
+        let _lock = lock();
+
+        let file = tempfile::tempfile().unwrap();
+        let (stream, _remote) = tcp_test::channel();
+
+        assert!(!is_unsupported(&file, &stream));
+
+        mark_unsupported(&file, &stream);
+        mark_unsupported(&file, &stream);
+
+        assert!(is_unsupported(&file, &stream));
+        assert_eq!(COUNT.load(Ordering::Relaxed), 1);
+
+        // another file behind the same descriptor is not affected, and the stale pair is forgotten
+        let other = tempfile::tempfile().unwrap();
+        assert_ne!(
+            unsafe { libc::dup2(other.as_raw_fd(), file.as_raw_fd()) },
+            -1
+        );
+
+        assert!(!is_unsupported(&file, &stream));
+        assert_eq!(COUNT.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn limit() {
+        let _lock = lock();
+
+        let files: Vec<File> = (0..=MAX_ENTRIES)
+            .map(|_| tempfile::tempfile().unwrap())
+            .collect();
+        let (stream, _remote) = tcp_test::channel();
+
+        for file in &files {
+            mark_unsupported(file, &stream);
+        }
+
+        assert_eq!(COUNT.load(Ordering::Relaxed), MAX_ENTRIES);
+        assert!(!is_unsupported(&files[0], &stream));
+        assert!(files[1..].iter().all(|file| is_unsupported(file, &stream)));
+    }
+
+    #[test]
+    fn fallback() {
+        let _lock = lock();
+
+        let mut file = tempfile::tempfile().unwrap();
+        file.write_all(b"dR#QaIw,").unwrap();
+
+        let (mut stream, mut remote) = tcp_test::channel();
+
+        mark_unsupported(&file, &stream);
+
+        assert_eq!(
+            crate::imp::try_send_chunk(&file, &stream, 0, 8).unwrap(),
+            None
+        );
+        assert_eq!(crate::imp::send_chunk(&file, &stream, 2, 6).unwrap(), 6);
+        crate::imp::send_file(&mut file, &mut stream).unwrap();
+
+        let mut buf = [0; 14];
+        remote.read_exact(&mut buf).unwrap();
+
+        assert_eq!(&buf, b"#QaIw,dR#QaIw,");
+    }
+}
@@ -0,0 +1,215 @@
+use crate::CancelToken;
+
+use std::io;
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+/// The longest time between two checks for cancellation if a token cannot wake up a wait.
+#[cfg(unix)]
+const CANCEL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Blocks until the stream is writable or has an error pending.
+#[cfg(unix)]
+#[inline]
+pub fn writable(stream: &TcpStream) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    writable_fd(stream.as_raw_fd())
+}
+
+/// Blocks until the descriptor is writable or has an error pending.
+#[cfg(unix)]
+pub fn writable_fd(fd: libc::c_int) -> io::Result<()> {
+    let mut fds = [libc::pollfd {
+        fd,
+        events: libc::POLLOUT,
+        revents: 0,
+    }];
+
+    loop {
+        if poll(&mut fds, None)? != 0 {
+            return Ok(());
+        };
+    }
+}
+
+/// Blocks until the stream is readable, has reached the end, or has an error pending.
+#[cfg(unix)]
+pub fn readable(stream: &TcpStream) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut fds = [libc::pollfd {
+        fd: stream.as_raw_fd(),
+        events: libc::POLLIN,
+        revents: 0,
+    }];
+
+    loop {
+        if poll(&mut fds, None)? != 0 {
+            return Ok(());
+        };
+    }
+}
+
+/// Blocks until the stream is writable, has an error pending, or the token has been cancelled.
+#[inline]
+pub fn writable_or_cancelled(stream: &TcpStream, cancel: &CancelToken) -> io::Result<()> {
+    writable_until(stream, Some(cancel), None)
+}
+
+/// Blocks until the stream is writable, has an error pending, the token has been cancelled,
+/// or `deadline` has passed.
+#[cfg(unix)]
+pub fn writable_until(
+    stream: &TcpStream,
+    cancel: Option<&CancelToken>,
+    deadline: Option<Instant>,
+) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut fds = [
+        libc::pollfd {
+            fd: stream.as_raw_fd(),
+            events: libc::POLLOUT,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: cancel.map_or(-1, CancelToken::fd),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+
+    loop {
+        // the timeout is rounded up, so that the deadline has passed once `poll()` times out
+        let timeout = deadline.map(|deadline| {
+            deadline.saturating_duration_since(Instant::now()) + Duration::from_nanos(999_999)
+        });
+
+        let timeout = match (timeout, cancel.and_then(cancel_timeout)) {
+            (Some(timeout), Some(interval)) => Some(timeout.min(interval)),
+            (timeout, interval) => timeout.or(interval),
+        };
+
+        if poll(&mut fds, timeout)? != 0
+            || cancel.is_some_and(CancelToken::is_cancelled)
+            || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Ok(());
+        };
+    }
+}
+
+/// Blocks until `fd` is readable, the token has been cancelled, or `timeout` has passed.
+///
+/// A negative `fd` is ignored, which makes this sleep for `timeout` unless the token is cancelled.
+#[cfg(unix)]
+pub fn readable_or_cancelled(
+    fd: libc::c_int,
+    cancel: &CancelToken,
+    timeout: Duration,
+) -> io::Result<()> {
+    let mut fds = [
+        libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        },
+        libc::pollfd {
+            fd: cancel.fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        },
+    ];
+
+    let timeout = match cancel_timeout(cancel) {
+        Some(interval) => timeout.min(interval),
+        None => timeout,
+    };
+
+    poll(&mut fds, Some(timeout)).map(|_| ())
+}
+
+/// Returns how long a wait may take before checking the token,
+/// which is unlimited if the token can wake up the wait.
+#[cfg(unix)]
+#[inline]
+fn cancel_timeout(cancel: &CancelToken) -> Option<Duration> {
+    if cancel.fd() < 0 {
+        Some(CANCEL_INTERVAL)
+    } else {
+        None
+    }
+}
+
+/// Polls the descriptors, ignoring negative ones, and returns the amount of ready descriptors.
+///
+/// Being interrupted by a signal counts as a timeout.
+#[cfg(unix)]
+fn poll(fds: &mut [libc::pollfd], timeout: Option<Duration>) -> io::Result<usize> {
+    use std::io::{Error, ErrorKind};
+
+    let timeout = match timeout {
+        Some(timeout) => timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int,
+        None => -1,
+    };
+
+    match unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout) } {
+        -1 => {
+            let e = Error::last_os_error();
+
+            if e.kind() == ErrorKind::Interrupted {
+                Ok(0)
+            } else {
+                Err(e)
+            }
+        }
+        ready => Ok(ready as usize),
+    }
+}
+
+/// Yields to other threads, since there is no portable way to wait for writability.
+#[cfg(not(unix))]
+#[inline]
+pub fn writable(_stream: &TcpStream) -> io::Result<()> {
+    std::thread::yield_now();
+
+    Ok(())
+}
+
+/// Yields to other threads, since there is no portable way to wait for readability.
+#[cfg(not(unix))]
+#[inline]
+pub fn readable(_stream: &TcpStream) -> io::Result<()> {
+    std::thread::yield_now();
+
+    Ok(())
+}
+
+/// Yields to other threads, since there is no portable way to wait for writability.
+#[cfg(not(unix))]
+#[inline]
+pub fn writable_until(
+    _stream: &TcpStream,
+    _cancel: Option<&CancelToken>,
+    _deadline: Option<Instant>,
+) -> io::Result<()> {
+    std::thread::yield_now();
+
+    Ok(())
+}
+
+/// Sleeps for `timeout` in short steps, returning early if the token has been cancelled.
+#[cfg(not(unix))]
+pub fn sleep_or_cancelled(cancel: &CancelToken, timeout: Duration) -> io::Result<()> {
+    const STEP: Duration = Duration::from_millis(10);
+
+    let mut slept = Duration::from_secs(0);
+
+    while slept < timeout && !cancel.is_cancelled() {
+        std::thread::sleep(STEP);
+        slept += STEP;
+    }
+
+    Ok(())
+}
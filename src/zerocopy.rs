@@ -0,0 +1,367 @@
+/*!
+Sending memory without copying it into the kernel using `MSG_ZEROCOPY`.
+
+Unlike `sendfile()`, which moves file pages, this sends arbitrary userspace memory,
+for example a memory-mapped file or data that is already resident.
+The kernel references the memory directly instead of copying it,
+so it must not be changed or freed until the kernel reports that the transmission is complete.
+[`ZeroCopySender`] takes ownership of the buffers and keeps them until then.
+
+This module is only available on Linux.
+
+[`ZeroCopySender`]: struct.ZeroCopySender.html
+*/
+
+use libc::{c_int, c_void};
+use std::io::{self, Error, ErrorKind};
+use std::mem;
+use std::net::TcpStream;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+// from `linux/errqueue.h`, which `libc` does not provide
+const SO_EE_ORIGIN_ZEROCOPY: u8 = 5;
+const SO_EE_CODE_ZEROCOPY_COPIED: u8 = 1;
+
+/// A buffer which has been handed to the kernel.
+struct Pending<B> {
+    buf: Box<B>,
+    /// The sequence number of the first `send()` of this buffer.
+    first: u64,
+    /// The sequence number after the last `send()` of this buffer.
+    end: u64,
+    /// The amount of `send()` calls which have not been completed yet.
+    remaining: u64,
+}
+
+/// Sends buffers to a TCP stream using `MSG_ZEROCOPY`,
+/// keeping each buffer until the kernel no longer references it.
+///
+/// For small buffers, copying is usually faster than the page pinning and the completion notifications,
+/// so this is only worth it for large amounts of data.
+/// On loopback connections, the kernel always copies.
+/// In both cases, the data is sent nevertheless, and [`copied()`] reports how often the kernel copied.
+///
+/// If the sender is dropped while buffers are still referenced by the kernel,
+/// it blocks until the transmission is complete.
+///
+/// A stream can be used by several senders one after another,
+/// but only by one at a time, because they would receive each other's notifications.
+///
+/// # Example
+///
+/// ```no_run
+/// use snedfile::zerocopy::ZeroCopySender;
+/// # use std::io;
+/// # use std::net::TcpStream;
+///
+/// fn send_all(stream: &TcpStream, chunks: Vec<Vec<u8>>) -> io::Result<Vec<Vec<u8>>> {
+///     let mut sender = ZeroCopySender::new(stream)?;
+///
+///     for chunk in chunks {
+///         sender.send(chunk)?;
+///     }
+///
+///     // wait until the buffers can be reused
+///     sender.flush()
+/// }
+/// ```
+///
+/// [`copied()`]: #method.copied
+pub struct ZeroCopySender<'a, B: AsRef<[u8]>> {
+    stream: &'a TcpStream,
+    pending: Vec<Pending<B>>,
+    released: Vec<B>,
+    /// The sequence number of the next `send()`, counted from the first one of this sender.
+    next: u64,
+    /// The kernel's number of the first `send()` of this sender.
+    ///
+    /// The kernel counts the `send()` calls of a socket over its entire lifetime,
+    /// so this is only known once the first notification arrives.
+    base: Option<u32>,
+    copied: u64,
+}
+
+impl<'a, B: AsRef<[u8]>> ZeroCopySender<'a, B> {
+    /// Enables `SO_ZEROCOPY` on the stream.
+    ///
+    /// This fails if the kernel does not support `MSG_ZEROCOPY`, which was introduced in Linux 4.14.
+    pub fn new(stream: &'a TcpStream) -> io::Result<ZeroCopySender<'a, B>> {
+        let enable: c_int = 1;
+
+        if unsafe {
+            libc::setsockopt(
+                stream.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_ZEROCOPY,
+                &enable as *const c_int as *const c_void,
+                mem::size_of::<c_int>() as libc::socklen_t,
+            )
+        } == -1
+        {
+            return Err(Error::last_os_error());
+        };
+
+        Ok(ZeroCopySender {
+            stream,
+            pending: Vec::new(),
+            released: Vec::new(),
+            next: 0,
+            base: None,
+            copied: 0,
+        })
+    }
+
+    /// Sends the entire buffer.
+    ///
+    /// The buffer is kept until the kernel reports that it is not referenced anymore,
+    /// after which it is returned by [`reclaim()`] or [`flush()`].
+    /// If an error occurs after a part of the buffer has been sent, the buffer is kept as well.
+    ///
+    /// `WouldBlock` and `Interrupted` errors are handled.
+    ///
+    /// [`reclaim()`]: #method.reclaim
+    /// [`flush()`]: #method.flush
+    pub fn send(&mut self, buf: B) -> io::Result<()> {
+        // the buffer is boxed so that its memory does not move while the kernel references it
+        let buf = Box::new(buf);
+        let fd = self.stream.as_raw_fd();
+        let first = self.next;
+
+        let mut offset = 0;
+        let mut result = Ok(());
+
+        while offset < (*buf).as_ref().len() {
+            let data = &(*buf).as_ref()[offset..];
+
+            match unsafe {
+                libc::send(
+                    fd,
+                    data.as_ptr() as *const c_void,
+                    data.len(),
+                    libc::MSG_ZEROCOPY | libc::MSG_NOSIGNAL,
+                )
+            } {
+                -1 => {
+                    let e = Error::last_os_error();
+
+                    match e.kind() {
+                        ErrorKind::Interrupted => continue,
+                        ErrorKind::WouldBlock => {
+                            if let Err(e) = wait(fd, libc::POLLOUT) {
+                                result = Err(e);
+                                break;
+                            };
+                        }
+                        // the memory which may be pinned is exhausted until notifications are read
+                        _ if e.raw_os_error() == Some(libc::ENOBUFS)
+                            && !self.pending.is_empty() =>
+                        {
+                            if let Err(e) = self.read_notifications(true) {
+                                result = Err(e);
+                                break;
+                            };
+                        }
+                        _ => {
+                            result = Err(e);
+                            break;
+                        }
+                    };
+                }
+                sent => {
+                    offset += sent as usize;
+                    self.next += 1;
+                }
+            };
+        }
+
+        if self.next > first {
+            self.pending.push(Pending {
+                buf,
+                first,
+                end: self.next,
+                remaining: self.next - first,
+            });
+        } else {
+            self.released.push(*buf);
+        };
+
+        result
+    }
+
+    /// Returns the buffers which the kernel does not reference anymore, without blocking.
+    pub fn reclaim(&mut self) -> io::Result<Vec<B>> {
+        self.read_notifications(false)?;
+
+        Ok(mem::take(&mut self.released))
+    }
+
+    /// Blocks until the kernel does not reference any buffer anymore, and returns them.
+    pub fn flush(&mut self) -> io::Result<Vec<B>> {
+        while !self.pending.is_empty() {
+            self.read_notifications(true)?;
+        }
+
+        Ok(mem::take(&mut self.released))
+    }
+
+    /// Returns the amount of buffers which are still referenced by the kernel.
+    #[inline]
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Returns how many `send()` calls were completed by copying the data instead,
+    /// which the kernel does if the network device does not support zero-copy transmission.
+    #[inline]
+    pub fn copied(&self) -> u64 {
+        self.copied
+    }
+
+    /// Reads all completion notifications from the error queue of the socket.
+    ///
+    /// If `block` is `true` and none are available, this waits for at least one.
+    fn read_notifications(&mut self, block: bool) -> io::Result<()> {
+        let fd = self.stream.as_raw_fd();
+
+        let mut received = false;
+
+        loop {
+            match self.read_notification(fd) {
+                Ok(Some((lo, hi, copied))) => {
+                    received = true;
+                    self.complete(lo, hi, copied);
+                }
+                Ok(None) if block && !received && !self.pending.is_empty() => {
+                    // the error queue is signalled using `POLLERR`, which is always reported
+                    wait(fd, 0)?;
+                }
+                Ok(None) => return Ok(()),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+        }
+    }
+
+    /// Reads one message from the error queue,
+    /// returning the range of completed `send()` calls and whether they were copied.
+    fn read_notification(&mut self, fd: RawFd) -> io::Result<Option<(u32, u32, bool)>> {
+        // aligned for `cmsghdr`
+        let mut control = [0u64; 16];
+
+        // messages which are not zero-copy notifications are skipped
+        loop {
+            let mut msg: libc::msghdr = unsafe { mem::zeroed() };
+            msg.msg_control = control.as_mut_ptr() as *mut c_void;
+            msg.msg_controllen = mem::size_of_val(&control) as _;
+
+            if unsafe { libc::recvmsg(fd, &mut msg, libc::MSG_ERRQUEUE) } == -1 {
+                let e = Error::last_os_error();
+
+                return if e.kind() == ErrorKind::WouldBlock {
+                    Ok(None)
+                } else {
+                    Err(e)
+                };
+            };
+
+            let mut cmsg = unsafe { libc::CMSG_FIRSTHDR(&msg) };
+
+            while !cmsg.is_null() {
+                let header = unsafe { &*cmsg };
+
+                if (header.cmsg_level == libc::SOL_IP && header.cmsg_type == libc::IP_RECVERR)
+                    || (header.cmsg_level == libc::SOL_IPV6
+                        && header.cmsg_type == libc::IPV6_RECVERR)
+                {
+                    let err: libc::sock_extended_err = unsafe {
+                        (libc::CMSG_DATA(cmsg) as *const libc::sock_extended_err).read_unaligned()
+                    };
+
+                    if err.ee_errno == 0 && err.ee_origin == SO_EE_ORIGIN_ZEROCOPY {
+                        return Ok(Some((
+                            err.ee_info,
+                            err.ee_data,
+                            err.ee_code & SO_EE_CODE_ZEROCOPY_COPIED != 0,
+                        )));
+                    };
+                };
+
+                cmsg = unsafe { libc::CMSG_NXTHDR(&msg, cmsg) };
+            }
+        }
+    }
+
+    /// Marks the `send()` calls `lo..=hi` as completed and releases the buffers which are done.
+    fn complete(&mut self, lo: u32, hi: u32, copied: bool) {
+        let oldest = match self.pending.first() {
+            Some(pending) => pending.first,
+            None => return,
+        };
+
+        // notifications arrive in order, so the first one starts at the oldest pending `send()`
+        let base = *self
+            .base
+            .get_or_insert_with(|| lo.wrapping_sub(oldest as u32));
+        let (lo, hi) = (lo.wrapping_sub(base), hi.wrapping_sub(base));
+
+        // the kernel counts using 32 bits, which are extended relative to the oldest pending buffer
+        let lo = oldest + u64::from(lo.wrapping_sub(oldest as u32));
+        let hi = lo + u64::from(hi.wrapping_sub(lo as u32)) + 1;
+
+        if copied {
+            self.copied += hi - lo;
+        };
+
+        for pending in self.pending.iter_mut() {
+            let start = pending.first.max(lo);
+            let end = pending.end.min(hi);
+
+            if start < end {
+                pending.remaining -= end - start;
+            };
+        }
+
+        let mut index = 0;
+
+        while index < self.pending.len() {
+            if self.pending[index].remaining == 0 {
+                let pending = self.pending.remove(index);
+                self.released.push(*pending.buf);
+            } else {
+                index += 1;
+            };
+        }
+    }
+}
+
+impl<'a, B: AsRef<[u8]>> Drop for ZeroCopySender<'a, B> {
+    fn drop(&mut self) {
+        if self.flush().is_err() {
+            // the kernel may still reference the buffers, so they must never be freed
+            for pending in self.pending.drain(..) {
+                mem::forget(pending.buf);
+            }
+        };
+    }
+}
+
+/// Waits until one of `events` or an error is signalled on the socket.
+fn wait(fd: RawFd, events: libc::c_short) -> io::Result<()> {
+    let mut poll = libc::pollfd {
+        fd,
+        events,
+        revents: 0,
+    };
+
+    loop {
+        if unsafe { libc::poll(&mut poll, 1, -1) } == -1 {
+            let e = Error::last_os_error();
+
+            if e.kind() != ErrorKind::Interrupted {
+                return Err(e);
+            };
+        } else {
+            return Ok(());
+        };
+    }
+}
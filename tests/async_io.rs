@@ -0,0 +1,41 @@
+#![cfg(feature = "async-io")]
+
+use tcp_test::*;
+
+use async_io::{block_on, Async};
+
+use std::fs::File;
+use std::io::Read;
+
+#[test]
+fn entire_file() {
+    let (local, mut remote) = channel();
+
+    let local = Async::new(local).unwrap();
+
+    let read_handle = File::open("tests/test_file").unwrap();
+
+    block_on(snedfile::async_io::send_file(&read_handle, &local)).expect("send_file() failed");
+
+    let mut buf = [0; 13];
+    remote.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"Hello world!\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn unix_stream() {
+    use std::os::unix::net::UnixStream;
+
+    let (local, mut remote) = UnixStream::pair().unwrap();
+
+    let local = Async::new(local).unwrap();
+
+    let read_handle = File::open("tests/test_file").unwrap();
+
+    block_on(snedfile::async_io::send_file(&read_handle, &local)).expect("send_file() failed");
+
+    let mut buf = [0; 13];
+    remote.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"Hello world!\n");
+}
@@ -0,0 +1,78 @@
+#![cfg(all(target_os = "linux", feature = "io-uring"))]
+
+use tcp_test::*;
+
+use snedfile::io_uring::Ring;
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn batch() {
+    let (first_local, mut first_remote) = channel();
+    let (second_local, mut second_remote) = channel();
+
+    let first_file = File::open("tests/test_file").unwrap();
+    let mut second_file = tempfile::tempfile().unwrap();
+    let data: Vec<u8> = (0..200_000u32).map(|i| (i % 241) as u8).collect();
+
+    second_file.write_all(&data).unwrap();
+
+    // the second transfer is larger than the socket buffer, so it needs to be read concurrently
+    let reader = std::thread::spawn(move || {
+        let mut buf = vec![0; 200_000];
+        second_remote.read_exact(&mut buf).unwrap();
+        buf
+    });
+
+    let mut ring = Ring::new(8).unwrap();
+    let results = ring
+        .send_files(&[(&first_file, &first_local), (&second_file, &second_local)])
+        .unwrap();
+
+    assert_eq!(results.len(), 2);
+    assert_eq!(*results[0].as_ref().unwrap(), 13);
+    assert_eq!(*results[1].as_ref().unwrap(), 200_000);
+
+    let mut buf = [0; 13];
+    first_remote.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"Hello world!\n");
+    assert_eq!(reader.join().unwrap(), data);
+}
+
+#[test]
+fn nonblocking() {
+    let (local, mut remote) = channel();
+
+    local.set_nonblocking(true).unwrap();
+
+    let mut file = tempfile::tempfile().unwrap();
+    let data: Vec<u8> = (0..8 * 1024 * 1024u32).map(|i| (i % 241) as u8).collect();
+
+    file.write_all(&data).unwrap();
+
+    // the reader is slow, so the stream stops being writable many times
+    let reader = thread::spawn(move || {
+        let mut received = Vec::new();
+        let mut buf = [0; 64 * 1024];
+
+        loop {
+            match remote.read(&mut buf).unwrap() {
+                0 => return received,
+                read => received.extend_from_slice(&buf[..read]),
+            };
+
+            thread::sleep(Duration::from_millis(1));
+        }
+    });
+
+    let mut ring = Ring::new(8).unwrap();
+    let results = ring.send_files(&[(&file, &local)]).unwrap();
+
+    assert_eq!(*results[0].as_ref().unwrap(), data.len() as u64);
+
+    drop(local);
+    assert!(reader.join().unwrap() == data);
+}
@@ -0,0 +1,106 @@
+#![cfg(feature = "mio")]
+
+use tcp_test::*;
+
+use mio::net::TcpStream;
+use mio::{Events, Poll, Token};
+use snedfile::mio::{Progress, Transfer};
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::thread;
+use std::time::Duration;
+
+#[test]
+fn entire_file() {
+    let (local, mut remote) = channel();
+
+    local.set_nonblocking(true).unwrap();
+    let mut local = TcpStream::from_std(local);
+
+    let mut poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(4);
+    let mut transfer = Transfer::new(File::open("tests/test_file").unwrap()).unwrap();
+
+    transfer
+        .register(poll.registry(), &mut local, Token(0))
+        .unwrap();
+
+    let sent = 'outer: loop {
+        poll.poll(&mut events, None).unwrap();
+
+        for event in events.iter() {
+            if event.is_writable() {
+                if let Progress::Done(sent) = transfer.advance(&local).unwrap() {
+                    break 'outer sent;
+                };
+            };
+        }
+    };
+
+    assert_eq!(sent, 13);
+
+    let mut buf = [0; 13];
+    remote.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"Hello world!\n");
+}
+
+#[test]
+fn slow_reader() {
+    let data: Vec<u8> = (0..8 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(&data).unwrap();
+
+    let (local, mut remote) = channel();
+
+    local.set_nonblocking(true).unwrap();
+    let mut local = TcpStream::from_std(local);
+
+    // the socket buffers fill up long before the end, so the transfer is resumed many times
+    let reader = thread::spawn(move || {
+        let mut received = Vec::new();
+        let mut buf = [0; 64 * 1024];
+
+        loop {
+            match remote.read(&mut buf).unwrap() {
+                0 => return received,
+                read => received.extend_from_slice(&buf[..read]),
+            };
+
+            thread::sleep(Duration::from_millis(1));
+        }
+    });
+
+    let mut poll = Poll::new().unwrap();
+    let mut events = Events::with_capacity(4);
+    let mut transfer = Transfer::new(file).unwrap();
+
+    transfer
+        .register(poll.registry(), &mut local, Token(0))
+        .unwrap();
+
+    let mut pending = 0;
+
+    let sent = 'outer: loop {
+        poll.poll(&mut events, None).unwrap();
+
+        for event in events.iter() {
+            if event.is_writable() {
+                match transfer.advance(&local).unwrap() {
+                    Progress::Done(sent) => break 'outer sent,
+                    Progress::Pending(sent) => {
+                        assert_eq!(sent, transfer.sent());
+                        pending += 1;
+                    }
+                };
+            };
+        }
+    };
+
+    assert_eq!(sent, data.len() as u64);
+    assert!(pending > 0);
+
+    drop(local);
+    assert!(reader.join().unwrap() == data);
+}
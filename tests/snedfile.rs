@@ -33,3 +33,786 @@ fn exact() {
     remote.read_exact(&mut buf).unwrap();
     assert_eq!(&buf, b"world Hello!\n");
 }
+
+#[test]
+fn cache_policies() {
+    for &policy in &[
+        CachePolicy::Normal,
+        CachePolicy::Sequential,
+        CachePolicy::NoReuse,
+        CachePolicy::Prefetch,
+    ] {
+        let (mut local, mut remote) = channel();
+
+        let mut read_handle = File::open("tests/test_file").unwrap();
+
+        send_file_with_policy(&mut read_handle, &mut local, policy)
+            .expect("send_file_with_policy() failed");
+
+        let mut buf = [0; 13];
+        remote.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"Hello world!\n");
+    }
+}
+
+/// Checks that the hints are applied, using the page cache residency of a file on disk.
+#[cfg(target_os = "linux")]
+#[test]
+fn cache_hints() {
+    use std::io::Write;
+    use std::thread;
+
+    const LENGTH: usize = 32 * 1024 * 1024;
+
+    let mut file = tempfile::tempfile_in(env!("CARGO_TARGET_TMPDIR")).unwrap();
+    file.write_all(&vec![7; LENGTH]).unwrap();
+    // only clean pages can be dropped
+    file.sync_all().unwrap();
+
+    let transfer = |file: &mut File, policy| {
+        let (mut local, mut remote) = channel();
+
+        // pages are only dropped once the socket buffers no longer refer to them
+        let receiver = thread::spawn(move || {
+            let mut buf = Vec::new();
+            remote.read_to_end(&mut buf).unwrap();
+            buf.len()
+        });
+
+        send_file_with_policy(file, &mut local, policy).unwrap();
+        drop(local);
+
+        assert_eq!(receiver.join().unwrap(), LENGTH);
+    };
+
+    let cached = |file: &mut File| {
+        let (mut local, _remote) = channel();
+
+        send_exact_nodiskio(file, &mut local, 4096, 0).unwrap() != NoDiskIo::WouldBlockOnDisk
+    };
+
+    transfer(&mut file, CachePolicy::Normal);
+    assert!(cached(&mut file), "the pages were dropped without a hint");
+
+    transfer(&mut file, CachePolicy::NoReuse);
+    assert!(!cached(&mut file), "the pages were not dropped");
+
+    // the pages are read asynchronously
+    prefetch(&file, 0, LENGTH as u64).unwrap();
+    assert!(
+        (0..100).any(|_| {
+            thread::sleep(std::time::Duration::from_millis(50));
+            cached(&mut file)
+        }),
+        "the pages were not read ahead"
+    );
+}
+
+#[test]
+fn nodiskio() {
+    let (mut local, mut remote) = channel();
+
+    let mut read_handle = File::open("tests/test_file").unwrap();
+
+    // reading the file makes sure it is in the page cache
+    read_handle.read_exact(&mut [0; 13]).unwrap();
+
+    let result = send_exact_nodiskio(&mut read_handle, &mut local, 13, 0)
+        .expect("send_exact_nodiskio() failed");
+    assert_eq!(result, NoDiskIo::Sent(13));
+
+    let mut buf = [0; 13];
+    remote.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"Hello world!\n");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn zerocopy() {
+    use snedfile::zerocopy::ZeroCopySender;
+
+    let (local, mut remote) = channel();
+
+    let mut sender = ZeroCopySender::new(&local).expect("SO_ZEROCOPY failed");
+
+    sender.send(b"Hello ".to_vec()).unwrap();
+    sender.send(b"world!\n".to_vec()).unwrap();
+
+    let buffers = sender.flush().unwrap();
+    assert_eq!(buffers, vec![b"Hello ".to_vec(), b"world!\n".to_vec()]);
+    assert_eq!(sender.pending(), 0);
+
+    let mut buf = [0; 13];
+    remote.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"Hello world!\n");
+
+    // the kernel keeps counting the `send()` calls of the stream for the next sender
+    drop(sender);
+    let mut sender = ZeroCopySender::new(&local).expect("SO_ZEROCOPY failed");
+
+    sender.send(b"again\n".to_vec()).unwrap();
+
+    let buffers = sender.flush().unwrap();
+    assert_eq!(buffers, vec![b"again\n".to_vec()]);
+
+    let mut buf = [0; 6];
+    remote.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"again\n");
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn splice() {
+    let (mut local, mut remote) = channel();
+
+    let mut read_handle = File::open("tests/test_file").unwrap();
+
+    // the second transfer reuses the pipe of the first one
+    snedfile::splice::send_file(&mut read_handle, &mut local).expect("splice::send_file() failed");
+    snedfile::splice::send_file(&mut read_handle, &mut local).expect("splice::send_file() failed");
+
+    let mut buf = [0; 26];
+    remote.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"Hello world!\nHello world!\n");
+
+    let read_handle = File::open("tests/test_file").unwrap();
+    assert_eq!(snedfile::splice::copy(&read_handle, &local).unwrap(), 13);
+
+    let mut buf = [0; 13];
+    remote.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"Hello world!\n");
+}
+
+#[test]
+fn proxy() {
+    use std::io::Write;
+    use std::net::Shutdown;
+    use std::thread;
+
+    let (mut client, client_side) = channel();
+    let (upstream_side, mut upstream) = channel();
+
+    let relay = thread::spawn(move || snedfile::proxy(&client_side, &upstream_side));
+
+    client.write_all(b"Hello").unwrap();
+    client.shutdown(Shutdown::Write).unwrap();
+
+    let mut buf = Vec::new();
+    upstream.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"Hello");
+
+    upstream.write_all(b"world!\n").unwrap();
+    upstream.shutdown(Shutdown::Write).unwrap();
+
+    let mut buf = Vec::new();
+    client.read_to_end(&mut buf).unwrap();
+    assert_eq!(buf, b"world!\n");
+
+    assert_eq!(relay.join().unwrap().unwrap(), (5, 7));
+}
+
+#[test]
+fn broadcast() {
+    let (first, mut first_remote) = channel();
+    let (second, mut second_remote) = channel();
+
+    let read_handle = File::open("tests/test_file").unwrap();
+
+    let results = snedfile::broadcast(&read_handle, &[&first, &second]).unwrap();
+    assert_eq!(results.len(), 2);
+
+    for result in results {
+        assert_eq!(result.unwrap(), 13);
+    }
+
+    for remote in &mut [&mut first_remote, &mut second_remote] {
+        let mut buf = [0; 13];
+        remote.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"Hello world!\n");
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn broadcast_stalled() {
+    use std::io::Write;
+    use std::time::Duration;
+
+    const LENGTH: usize = 16 * 1024 * 1024;
+
+    let (first, mut first_remote) = channel();
+    let (second, _second_remote) = channel();
+
+    second
+        .set_write_timeout(Some(Duration::from_millis(200)))
+        .unwrap();
+
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(&vec![1; LENGTH]).unwrap();
+
+    let receiver = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        first_remote.read_to_end(&mut buf).unwrap();
+        buf.len()
+    });
+
+    let mut results = snedfile::broadcast(&file, &[&first, &second]).unwrap();
+    drop(first);
+
+    assert_eq!(
+        results.pop().unwrap().unwrap_err().kind(),
+        std::io::ErrorKind::TimedOut
+    );
+    assert_eq!(results.pop().unwrap().unwrap(), LENGTH as u64);
+    assert_eq!(receiver.join().unwrap(), LENGTH);
+}
+
+#[test]
+fn recv() {
+    use std::io::{Seek, SeekFrom, Write};
+    use std::net::Shutdown;
+
+    let (mut local, mut remote) = channel();
+
+    remote.write_all(b"Hello world!\nrest").unwrap();
+    remote.shutdown(Shutdown::Write).unwrap();
+
+    let mut file = tempfile::tempfile().unwrap();
+
+    let options = RecvOptions {
+        preallocate: true,
+        sync: true,
+    };
+
+    assert_eq!(recv_file(&mut local, &mut file, 13, options).unwrap(), 13);
+    assert_eq!(file.metadata().unwrap().len(), 13);
+    assert_eq!(
+        recv_to_end(&mut local, &mut file, RecvOptions::default()).unwrap(),
+        4
+    );
+
+    let mut buf = String::new();
+    file.seek(SeekFrom::Start(0)).unwrap();
+    file.read_to_string(&mut buf).unwrap();
+    assert_eq!(buf, "Hello world!\nrest");
+}
+
+#[test]
+fn recv_nonblocking() {
+    use std::fs::OpenOptions;
+    use std::io::{Seek, SeekFrom, Write};
+    use std::time::Duration;
+
+    // the file opened for appending is received using `read()` instead of `splice()`
+    let path = tempfile::NamedTempFile::new().unwrap().into_temp_path();
+    let files = [
+        tempfile::tempfile().unwrap(),
+        OpenOptions::new()
+            .read(true)
+            .append(true)
+            .open(&path)
+            .unwrap(),
+    ];
+
+    for mut file in files {
+        let (mut local, mut remote) = channel();
+
+        local.set_nonblocking(true).unwrap();
+
+        let sender = std::thread::spawn(move || {
+            remote.write_all(b"Hello ").unwrap();
+            std::thread::sleep(Duration::from_millis(50));
+            remote.write_all(b"world!\n").unwrap();
+        });
+
+        assert_eq!(
+            recv_file(&mut local, &mut file, 13, RecvOptions::default()).unwrap(),
+            13
+        );
+        sender.join().unwrap();
+
+        let mut buf = String::new();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "Hello world!\n");
+    }
+}
+
+#[test]
+fn copy_file() {
+    use std::io::{Seek, SeekFrom};
+
+    let src = File::open("tests/test_file").unwrap();
+    let mut dst = tempfile::tempfile().unwrap();
+
+    let (copied, _) = snedfile::copy_file(&src, &dst, 6..12).unwrap();
+    assert_eq!(copied, 6);
+    let (copied, _) = snedfile::copy_file(&src, &dst, ..).unwrap();
+    assert_eq!(copied, 13);
+
+    let mut buf = String::new();
+    dst.seek(SeekFrom::Start(0)).unwrap();
+    dst.read_to_string(&mut buf).unwrap();
+    assert_eq!(buf, "world!Hello world!\n");
+}
+
+#[test]
+fn framed() {
+    use snedfile::framed::{receive_framed, send_framed};
+    use std::thread;
+
+    let (mut local, mut remote) = channel();
+    let dir = tempfile::tempdir().unwrap();
+
+    let sender = thread::spawn(move || {
+        let mut read_handle = File::open("tests/test_file").unwrap();
+
+        send_framed(&mut read_handle, &mut local, Some("hello.txt"), true).unwrap();
+        send_framed(&mut read_handle, &mut local, None, false).unwrap();
+    });
+
+    let named = receive_framed(&mut remote, dir.path()).unwrap();
+    assert_eq!(named.path, dir.path().join("hello.txt"));
+    assert_eq!(named.header.length, 13);
+    assert_eq!(named.header.name.as_deref(), Some("hello.txt"));
+    assert_eq!(named.header.checksum, Some(0xb2a9e441));
+
+    let unnamed = receive_framed(&mut remote, dir.path()).unwrap();
+    assert_eq!(unnamed.header.name, None);
+
+    sender.join().unwrap();
+
+    for path in &[named.path, unnamed.path] {
+        assert_eq!(std::fs::read(path).unwrap(), b"Hello world!\n");
+    }
+}
+
+#[test]
+fn framed_nonblocking() {
+    use snedfile::framed::{receive_framed, send_framed};
+    use std::io::{ErrorKind, Write};
+    use std::thread;
+    use std::time::Duration;
+
+    let (mut local, mut remote) = channel();
+    let dir = tempfile::tempdir().unwrap();
+
+    // the socket buffers are full, so even the header has to wait for the receiver
+    local.set_nonblocking(true).unwrap();
+
+    let mut filled = 0;
+
+    loop {
+        match local.write(&[0; 64 * 1024]) {
+            Ok(written) => filled += written,
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(e) => panic!("{}", e),
+        };
+    }
+
+    let receiver = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+
+        let mut buf = vec![0; filled];
+        remote.read_exact(&mut buf).unwrap();
+
+        (receive_framed(&mut remote, dir.path()).unwrap(), dir)
+    });
+
+    let mut read_handle = File::open("tests/test_file").unwrap();
+    send_framed(&mut read_handle, &mut local, Some("hello.txt"), true).unwrap();
+
+    let (received, _dir) = receiver.join().unwrap();
+    assert_eq!(received.header.checksum, Some(0xb2a9e441));
+    assert_eq!(std::fs::read(&received.path).unwrap(), b"Hello world!\n");
+}
+
+#[test]
+fn tar() {
+    use snedfile::tar::{Entry, TarWriter};
+    use std::path::Path;
+    use std::thread;
+
+    let (mut local, mut remote) = channel();
+
+    let receiver = thread::spawn(move || {
+        let mut archive = Vec::new();
+        remote.read_to_end(&mut archive).unwrap();
+        archive
+    });
+
+    let mut archive = TarWriter::new(&mut local);
+    let mut read_handle = File::open("tests/test_file").unwrap();
+
+    assert_eq!(
+        archive
+            .append_path(Path::new("tests/../tests/test_file"))
+            .unwrap_err()
+            .kind(),
+        std::io::ErrorKind::InvalidInput
+    );
+
+    assert_eq!(
+        archive.append_path(Path::new("tests/test_file")).unwrap(),
+        13
+    );
+    assert_eq!(
+        archive
+            .append_file(&Entry::new("copy.txt"), &mut read_handle)
+            .unwrap(),
+        13
+    );
+    assert_eq!(archive.written(), 4 * 512);
+    archive.finish().unwrap();
+    drop(local);
+
+    let archive = receiver.join().unwrap();
+    assert_eq!(archive.len(), 6 * 512);
+
+    assert!(archive.starts_with(b"tests/test_file\0"));
+    assert_eq!(&archive[124..136], b"00000000015\0");
+    assert_eq!(&archive[257..263], b"ustar\0");
+    assert_eq!(&archive[512..525], b"Hello world!\n");
+    assert!(archive[1024..].starts_with(b"copy.txt\0"));
+    assert!(archive[4 * 512..].iter().all(|&byte| byte == 0));
+}
+
+#[test]
+fn files() {
+    use std::io::Write;
+    use std::thread;
+
+    let (mut local, mut remote) = channel();
+
+    let first = File::open("tests/test_file").unwrap();
+    let mut second = tempfile::tempfile().unwrap();
+    second.write_all(&[7; 1024 * 1024]).unwrap();
+
+    let receiver = thread::spawn(move || {
+        let mut buf = Vec::new();
+        remote.read_to_end(&mut buf).unwrap();
+        buf
+    });
+
+    // the receiver is slower than the sender, which has to wait for writability
+    local.set_nonblocking(true).unwrap();
+
+    let sent = send_files(&[&first, &second, &first], &mut local).unwrap();
+    assert_eq!(sent, vec![13, 1024 * 1024, 13]);
+    drop(local);
+
+    let buf = receiver.join().unwrap();
+    assert_eq!(buf.len(), 13 + 1024 * 1024 + 13);
+    assert_eq!(&buf[..13], b"Hello world!\n");
+    assert!(buf[13..13 + 1024 * 1024].iter().all(|&byte| byte == 7));
+    assert_eq!(&buf[13 + 1024 * 1024..], b"Hello world!\n");
+}
+
+#[test]
+fn follow() {
+    use std::io::Write;
+    use std::thread;
+
+    let (mut local, mut remote) = channel();
+
+    let mut log = tempfile::tempfile().unwrap();
+    log.write_all(b"Hello ").unwrap();
+
+    let cancel = CancelToken::new();
+
+    let follower = {
+        let log = log.try_clone().unwrap();
+        let cancel = cancel.clone();
+
+        thread::spawn(move || snedfile::follow(&log, &mut local, &cancel).unwrap())
+    };
+
+    let mut buf = [0; 6];
+    remote.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"Hello ");
+
+    log.write_all(b"world!\n").unwrap();
+
+    let mut buf = [0; 7];
+    remote.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"world!\n");
+
+    cancel.cancel();
+    assert_eq!(follower.join().unwrap(), 13);
+}
+
+#[test]
+fn ship_log() {
+    use snedfile::ship::{ship_log, Checkpoint};
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+    use std::thread;
+
+    let (mut local, mut remote) = channel();
+
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("log");
+    let checkpoint = dir.path().join("checkpoint");
+
+    fs::write(&log, b"Hello ").unwrap();
+
+    let cancel = CancelToken::new();
+
+    let shipper = {
+        let log = log.clone();
+        let checkpoint = checkpoint.clone();
+        let cancel = cancel.clone();
+
+        thread::spawn(move || ship_log(&log, &mut local, &checkpoint, &cancel).unwrap())
+    };
+
+    let mut buf = [0; 6];
+    remote.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"Hello ");
+
+    // rotate the log while the old file is still written to
+    let mut old = OpenOptions::new().append(true).open(&log).unwrap();
+    fs::rename(&log, dir.path().join("log.1")).unwrap();
+    old.write_all(b"world!\n").unwrap();
+    fs::write(&log, b"rotated\n").unwrap();
+
+    let mut buf = [0; 15];
+    remote.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"world!\nrotated\n");
+
+    cancel.cancel();
+    assert_eq!(shipper.join().unwrap(), 21);
+
+    let stored = Checkpoint::load(&checkpoint).unwrap().unwrap();
+    assert_eq!(stored.offset, 8);
+}
+
+#[test]
+fn resumable() {
+    use snedfile::resume::send_resumable;
+    use std::io::{ErrorKind, Write};
+
+    let file = File::open("tests/test_file").unwrap();
+
+    let (mut local, mut remote) = channel();
+    let mut checkpoints = Vec::new();
+
+    let last = send_resumable(&file, &mut local, None, 4, |checkpoint| {
+        checkpoints.push(*checkpoint);
+        Ok(())
+    })
+    .unwrap();
+    drop(local);
+
+    let offsets: Vec<u64> = checkpoints
+        .iter()
+        .map(|checkpoint| checkpoint.offset)
+        .collect();
+    assert_eq!(offsets, [4, 8, 12, 13]);
+    assert!(last.is_complete());
+
+    let mut buf = String::new();
+    remote.read_to_string(&mut buf).unwrap();
+    assert_eq!(buf, "Hello world!\n");
+
+    // resume after "Hello "
+    let mut resume = checkpoints[0];
+    resume.offset = 6;
+
+    let (mut local, mut remote) = channel();
+    send_resumable(&file, &mut local, Some(&resume), 4, |_| Ok(())).unwrap();
+    drop(local);
+
+    let mut buf = String::new();
+    remote.read_to_string(&mut buf).unwrap();
+    assert_eq!(buf, "world!\n");
+
+    // a changed file is not resumed
+    let mut changed = tempfile::tempfile().unwrap();
+    changed.write_all(b"Hello world!\n").unwrap();
+
+    let (mut local, _remote) = channel();
+    let e = send_resumable(&changed, &mut local, Some(&resume), 4, |_| Ok(())).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn progress() {
+    use std::io::{ErrorKind, Write};
+
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(&vec![7; 1024 * 1024]).unwrap();
+
+    let (mut local, mut remote) = channel();
+    local.set_nonblocking(true).unwrap();
+
+    let receiver = std::thread::spawn(move || {
+        let mut buf = Vec::new();
+        remote.read_to_end(&mut buf).unwrap();
+        buf.len()
+    });
+
+    let mut reports = Vec::new();
+
+    let sent = send_file_with_progress(
+        &file,
+        &mut local,
+        Granularity::Bytes(4096),
+        |sent, total| {
+            reports.push((sent, total));
+            Control::Continue
+        },
+    )
+    .unwrap();
+    drop(local);
+
+    assert_eq!(sent, 1024 * 1024);
+    assert_eq!(receiver.join().unwrap(), 1024 * 1024);
+    assert_eq!(reports.last(), Some(&(1024 * 1024, Some(1024 * 1024))));
+    assert!(reports.len() <= 1024 * 1024 / 4096 + 1);
+    assert!(reports.windows(2).all(|pair| pair[0].0 < pair[1].0));
+
+    // abort after the first chunk, which cannot send the entire file without a receiver
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(&vec![7; 64 * 1024 * 1024]).unwrap();
+
+    let (mut local, _remote) = channel();
+    local.set_nonblocking(true).unwrap();
+
+    let e = send_file_with_progress(&file, &mut local, Granularity::Bytes(4096), |_, _| {
+        Control::Abort
+    })
+    .unwrap_err();
+
+    assert_eq!(e.kind(), ErrorKind::Other);
+    let aborted = e.get_ref().unwrap().downcast_ref::<Aborted>().unwrap();
+    assert!(aborted.sent >= 4096 && aborted.sent <= 1024 * 1024);
+}
+
+#[test]
+fn cancel() {
+    use std::io::{ErrorKind, Write};
+    use std::thread;
+    use std::time::Duration;
+
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(&vec![7; 16 * 1024 * 1024]).unwrap();
+
+    // the peer does not read, so the transfer waits for the stream to become writable
+    let (mut local, _remote) = channel();
+    local.set_nonblocking(true).unwrap();
+
+    let cancel = CancelToken::new();
+
+    let canceller = {
+        let cancel = cancel.clone();
+
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            cancel.cancel();
+        })
+    };
+
+    let e = send_file_cancellable(&file, &mut local, &cancel).unwrap_err();
+    canceller.join().unwrap();
+
+    assert_eq!(e.kind(), ErrorKind::Other);
+    let cancelled = e.get_ref().unwrap().downcast_ref::<Cancelled>().unwrap();
+    assert!(cancelled.sent > 0 && cancelled.sent < 16 * 1024 * 1024);
+}
+
+#[test]
+fn options() {
+    use std::io::{ErrorKind, Write};
+    use std::time::Duration;
+
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(&vec![7; 16 * 1024 * 1024]).unwrap();
+
+    let (mut local, _remote) = channel();
+    local.set_nonblocking(true).unwrap();
+
+    // the callback cancels the token, and the idle timeout would end the transfer otherwise
+    let cancel = CancelToken::new();
+    let mut progress = |_, _| {
+        cancel.cancel();
+        Control::Continue
+    };
+
+    let options = SendOptions {
+        cancel: Some(&cancel),
+        timeouts: Timeouts {
+            deadline: None,
+            idle: Some(Duration::from_secs(5)),
+        },
+        progress: Some(&mut progress),
+        ..SendOptions::default()
+    };
+
+    let e = send_file_with_options(&file, &mut local, options).unwrap_err();
+
+    assert_eq!(e.kind(), ErrorKind::Other);
+    let cancelled = e.get_ref().unwrap().downcast_ref::<Cancelled>().unwrap();
+    assert!(cancelled.sent > 0 && cancelled.sent <= 1024 * 1024);
+}
+
+#[test]
+fn timeouts() {
+    use std::io::{ErrorKind, Write};
+    use std::time::{Duration, Instant};
+
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(&vec![7; 16 * 1024 * 1024]).unwrap();
+
+    let idle = Timeouts {
+        deadline: None,
+        idle: Some(Duration::from_millis(100)),
+    };
+
+    // the peer does not read, so no progress is made once the buffers are full
+    for &nonblocking in &[false, true] {
+        let (mut local, _remote) = channel();
+        local.set_nonblocking(nonblocking).unwrap();
+
+        let e = send_file_with_timeouts(&file, &mut local, idle).unwrap_err();
+
+        assert_eq!(e.kind(), ErrorKind::TimedOut);
+        let timed_out = e.get_ref().unwrap().downcast_ref::<TimedOut>().unwrap();
+        assert!(timed_out.idle);
+        assert!(timed_out.sent > 0 && timed_out.sent < 16 * 1024 * 1024);
+
+        assert_eq!(local.write_timeout().unwrap(), None);
+    }
+
+    let (mut local, _remote) = channel();
+    let start = Instant::now();
+
+    let e = send_file_with_timeouts(
+        &file,
+        &mut local,
+        Timeouts::total(Duration::from_millis(100)),
+    )
+    .unwrap_err();
+
+    assert_eq!(e.kind(), ErrorKind::TimedOut);
+    assert!(
+        !e.get_ref()
+            .unwrap()
+            .downcast_ref::<TimedOut>()
+            .unwrap()
+            .idle
+    );
+    assert!(start.elapsed() < Duration::from_secs(5));
+
+    // a transfer within the limits is not affected
+    let (mut local, mut remote) = channel();
+    let file = File::open("tests/test_file").unwrap();
+
+    let sent = send_file_with_timeouts(&file, &mut local, Timeouts::total(Duration::from_secs(5)))
+        .unwrap();
+    drop(local);
+
+    let mut buf = String::new();
+    remote.read_to_string(&mut buf).unwrap();
+    assert_eq!((sent, &*buf), (13, "Hello world!\n"));
+}
@@ -0,0 +1,81 @@
+#![cfg(feature = "tokio")]
+
+use tcp_test::*;
+
+use tokio::net::TcpStream;
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::thread;
+use std::time::Duration;
+
+#[tokio::test]
+async fn entire_file() {
+    let (local, mut remote) = channel();
+
+    local.set_nonblocking(true).unwrap();
+    let local = TcpStream::from_std(local).unwrap();
+
+    let read_handle = File::open("tests/test_file").unwrap();
+
+    snedfile::tokio::send_file(&read_handle, &local)
+        .await
+        .expect("send_file() failed");
+
+    let mut buf = [0; 13];
+    remote.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"Hello world!\n");
+}
+
+#[tokio::test]
+async fn tokio_file() {
+    let (local, mut remote) = channel();
+
+    local.set_nonblocking(true).unwrap();
+    let local = TcpStream::from_std(local).unwrap();
+
+    let read_handle = tokio::fs::File::open("tests/test_file").await.unwrap();
+
+    snedfile::tokio::send_file(&read_handle, &local)
+        .await
+        .expect("send_file() failed");
+
+    let mut buf = [0; 13];
+    remote.read_exact(&mut buf).unwrap();
+    assert_eq!(&buf, b"Hello world!\n");
+}
+
+#[tokio::test]
+async fn slow_reader() {
+    let data: Vec<u8> = (0..8 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(&data).unwrap();
+
+    let (local, mut remote) = channel();
+
+    local.set_nonblocking(true).unwrap();
+    let local = TcpStream::from_std(local).unwrap();
+
+    // the socket buffers fill up long before the end, so the transfer has to wait for the reader
+    let reader = thread::spawn(move || {
+        let mut received = Vec::new();
+        let mut buf = [0; 64 * 1024];
+
+        loop {
+            match remote.read(&mut buf).unwrap() {
+                0 => return received,
+                read => received.extend_from_slice(&buf[..read]),
+            };
+
+            thread::sleep(Duration::from_millis(1));
+        }
+    });
+
+    snedfile::tokio::send_file(&file, &local)
+        .await
+        .expect("send_file() failed");
+    drop(local);
+
+    assert!(reader.join().unwrap() == data);
+}